@@ -0,0 +1,86 @@
+//! 消息列表单帧耗时，消息数量从 100 到 100k，结果应该基本持平。
+//!
+//! 运行：`cargo bench --bench message_timeline`
+
+use bevy_egui::egui::{pos2, vec2, CentralPanel, Context, RawInput, Rect, ScrollArea};
+use chrono::{Duration, TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use my_lark::{
+    ChatMessage, DeliveryState, MessageContent, MessageTimeline, MessageType, TimelineRow,
+};
+
+const SENDERS: &[(&str, &str)] = &[("Alice", "A"), ("Bob", "B"), ("Carol", "C")];
+
+fn make_messages(count: usize) -> Vec<ChatMessage> {
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    (0..count)
+        .map(|i| {
+            let (sender, avatar) = SENDERS[(i / 3) % SENDERS.len()];
+            ChatMessage {
+                id: format!("{:08}", i),
+                client_key: String::new(),
+                chat_id: "bench".to_string(),
+                sender: sender.to_string(),
+                avatar: avatar.to_string(),
+                content: MessageContent::text(
+                    format!("第 {} 条消息，长度不一的内容用来测试换行。", i).repeat(1 + i % 4),
+                ),
+                timestamp: start + Duration::minutes(i as i64 * 7),
+                message_type: MessageType::Text,
+                delivery: DeliveryState::Sent,
+                reply_to: None,
+            }
+        })
+        .collect()
+}
+
+fn render_frame(ctx: &Context, timeline: &mut MessageTimeline, messages: &[ChatMessage]) {
+    let input = RawInput {
+        screen_rect: Some(Rect::from_min_size(pos2(0.0, 0.0), vec2(800.0, 600.0))),
+        ..Default::default()
+    };
+    let _ = ctx.run(input, |ctx| {
+        CentralPanel::default().show(ctx, |ui| {
+            timeline.update("bench", messages, 0);
+            ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .stick_to_bottom(true)
+                .show_viewport(ui, |ui, viewport| {
+                    timeline.show_rows(ui, viewport, |ui, row| match row {
+                        TimelineRow::Header => {
+                            ui.add_space(16.0);
+                        }
+                        TimelineRow::DaySeparator { index } => {
+                            ui.label(messages[index].timestamp.date_naive().to_string());
+                        }
+                        TimelineRow::Message { index, show_avatar } => {
+                            if show_avatar {
+                                ui.strong(&messages[index].sender);
+                            }
+                            ui.label(messages[index].content.plain_text());
+                        }
+                    });
+                });
+        });
+    });
+}
+
+fn frame_time(c: &mut Criterion) {
+    let mut group = c.benchmark_group("message_timeline_frame");
+    for count in [100, 1_000, 10_000, 100_000] {
+        let messages = make_messages(count);
+        let ctx = Context::default();
+        let mut timeline = MessageTimeline::default();
+        // 先跑几帧，让可见行的高度测量稳定下来
+        for _ in 0..3 {
+            render_frame(&ctx, &mut timeline, &messages);
+        }
+        group.bench_with_input(BenchmarkId::from_parameter(count), &messages, |b, messages| {
+            b.iter(|| render_frame(&ctx, &mut timeline, messages));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, frame_time);
+criterion_main!(benches);
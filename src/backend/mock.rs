@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::Duration,
+};
+
+use chrono::Utc;
+
+use crate::{
+    resources::{ChatData, ChatRoomData},
+    Chat, ChatMessage, ChatType, DeliveryState, MessageContent, MessageType, Presence,
+};
+
+use super::{BackendError, BackendEvent, ChatBackend, MessagePage, Waker};
+
+const SIMULATED_USERS: &[(&str, &str)] = &[("Alice", "A"), ("Bob", "B"), ("Carol", "C")];
+
+const SIMULATED_LINES: &[&str] = &[
+    "大家好 👋",
+    "今天的站会改到下午三点",
+    "PR 已经提了，麻烦帮忙 review 一下",
+    "收到",
+    "文档我更新到云文档里了",
+    "有人看到构建失败了吗？",
+];
+
+#[derive(Default)]
+struct MockState {
+    rooms: HashMap<String, ChatRoomData>,
+    pending: Vec<BackendEvent>,
+    send_attempts: u64,
+    /// 用户 -> (最后一次发消息的轮次, 上次通知的在线状态)
+    activity: HashMap<String, (usize, Presence)>,
+}
+
+impl MockState {
+    /// 记录 `active` 在第 `tick` 轮发了消息，其他用户按空闲的轮数变成离开或离线
+    fn update_presence(&mut self, tick: usize, active: &str) {
+        self.activity
+            .entry(active.to_string())
+            .or_insert((tick, Presence::Offline))
+            .0 = tick;
+        for (user, (last_active, reported)) in &mut self.activity {
+            let presence = presence_after_idle(tick - *last_active);
+            if presence != *reported {
+                *reported = presence;
+                self.pending.push(BackendEvent::PresenceChanged {
+                    user: user.clone(),
+                    presence,
+                });
+            }
+        }
+    }
+}
+
+/// 每隔多少次发送模拟一次网络错误，用来验证发件箱的重试
+const SIMULATED_FAILURE_EVERY: u64 = 5;
+
+/// 连续多少轮没发消息算离开、离线
+const AWAY_AFTER_TICKS: usize = 3;
+const OFFLINE_AFTER_TICKS: usize = 10;
+
+fn presence_after_idle(idle_ticks: usize) -> Presence {
+    if idle_ticks < AWAY_AFTER_TICKS {
+        Presence::Online
+    } else if idle_ticks < OFFLINE_AFTER_TICKS {
+        Presence::Away
+    } else {
+        Presence::Offline
+    }
+}
+
+/// 进程内的模拟服务端，定时以其他用户的身份往聊天里发消息
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+    interval: Duration,
+    subscribed: bool,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::with_interval(Duration::from_secs(20))
+    }
+
+    pub fn with_interval(interval: Duration) -> Self {
+        let state = MockState {
+            rooms: ChatData::create_default_chat_rooms(),
+            ..Default::default()
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            interval,
+            subscribed: false,
+        }
+    }
+
+    fn simulate(state: Weak<Mutex<MockState>>, interval: Duration, waker: Waker) {
+        let mut tick = 0usize;
+        loop {
+            thread::sleep(interval);
+            // 后端被释放后退出模拟线程
+            let Some(state) = state.upgrade() else {
+                break;
+            };
+            {
+                let mut state = state.lock().unwrap();
+                let mut chat_ids: Vec<String> = state.rooms.keys().cloned().collect();
+                if chat_ids.is_empty() {
+                    continue;
+                }
+                chat_ids.sort();
+                let chat_id = chat_ids[tick % chat_ids.len()].clone();
+                let Some(room) = state.rooms.get(&chat_id) else {
+                    continue;
+                };
+                let (sender, avatar) = match room.chat.chat_type {
+                    // 单聊和机器人只有对方会发消息
+                    ChatType::Direct | ChatType::Bot => {
+                        (room.chat.name.clone(), room.chat.avatar.clone())
+                    }
+                    _ => {
+                        let (sender, avatar) = SIMULATED_USERS[tick % SIMULATED_USERS.len()];
+                        (sender.to_string(), avatar.to_string())
+                    }
+                };
+                // 话题群里隔一条回复一次最新的话题
+                let reply_to = (room.chat.chat_type == ChatType::TopicGroup && tick % 2 == 1)
+                    .then(|| {
+                        room.messages
+                            .iter()
+                            .rev()
+                            .find(|msg| msg.reply_to.is_none())
+                            .map(|msg| msg.id.clone())
+                    })
+                    .flatten();
+                let message = ChatMessage {
+                    id: ChatMessage::generate_id(),
+                    client_key: String::new(),
+                    chat_id: chat_id.clone(),
+                    sender: sender.clone(),
+                    avatar,
+                    content: MessageContent::text(SIMULATED_LINES[tick % SIMULATED_LINES.len()]),
+                    timestamp: Utc::now(),
+                    message_type: MessageType::Text,
+                    delivery: DeliveryState::Sent,
+                    reply_to,
+                };
+                if let Some(room) = state.rooms.get_mut(&chat_id) {
+                    room.messages.push(message.clone());
+                    room.unread_count += 1;
+                }
+                state.update_presence(tick, &sender);
+                // 发消息的人同时读完了之前的消息，包括我们发的
+                state.pending.push(BackendEvent::MessagesRead {
+                    chat_id: chat_id.clone(),
+                    user: sender,
+                    read_at: message.timestamp,
+                });
+                state.pending.push(BackendEvent::MessageReceived(message));
+            }
+            tick += 1;
+            waker();
+        }
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChatBackend for MockBackend {
+    fn list_chats(&self) -> Vec<Chat> {
+        let state = self.state.lock().unwrap();
+        let mut chats: Vec<Chat> = state.rooms.values().map(|room| room.chat.clone()).collect();
+        chats.sort_by(|a, b| a.id.cmp(&b.id));
+        chats
+    }
+
+    fn unread_counts(&self) -> HashMap<String, i32> {
+        let state = self.state.lock().unwrap();
+        state
+            .rooms
+            .iter()
+            .map(|(id, room)| (id.clone(), room.unread_count))
+            .collect()
+    }
+
+    fn fetch_messages(&self, chat_id: &str, before: Option<&str>, limit: usize) -> MessagePage {
+        let state = self.state.lock().unwrap();
+        let Some(room) = state.rooms.get(chat_id) else {
+            return MessagePage::default();
+        };
+        let end = before
+            .and_then(|id| room.messages.iter().position(|msg| msg.id == id))
+            .unwrap_or(room.messages.len());
+        let start = end.saturating_sub(limit);
+        MessagePage {
+            messages: room.messages[start..end].to_vec(),
+            has_more: start > 0,
+        }
+    }
+
+    fn send_message(&mut self, message: ChatMessage) -> Result<ChatMessage, BackendError> {
+        let mut state = self.state.lock().unwrap();
+        state.send_attempts += 1;
+        if state.send_attempts.is_multiple_of(SIMULATED_FAILURE_EVERY) {
+            return Err(BackendError::Unavailable(
+                "simulated network error".to_string(),
+            ));
+        }
+        let room = state
+            .rooms
+            .get_mut(&message.chat_id)
+            .ok_or_else(|| BackendError::ChatNotFound(message.chat_id.clone()))?;
+        // 同一个幂等键的重发直接返回已保存的那条
+        if let Some(existing) = room
+            .messages
+            .iter()
+            .find(|msg| !message.client_key.is_empty() && msg.client_key == message.client_key)
+        {
+            return Ok(existing.clone());
+        }
+        let sent = ChatMessage {
+            id: ChatMessage::generate_id(),
+            timestamp: Utc::now(),
+            ..message
+        };
+        room.messages.push(sent.clone());
+        Ok(sent)
+    }
+
+    fn mark_read(&mut self, chat_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(room) = state.rooms.get_mut(chat_id) {
+            room.unread_count = 0;
+        }
+    }
+
+    fn subscribe(&mut self, waker: Waker) {
+        if self.subscribed {
+            return;
+        }
+        self.subscribed = true;
+        let state = Arc::downgrade(&self.state);
+        let interval = self.interval;
+        thread::spawn(move || Self::simulate(state, interval, waker));
+    }
+
+    fn poll_events(&mut self) -> Vec<BackendEvent> {
+        let mut state = self.state.lock().unwrap();
+        std::mem::take(&mut state.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presence_changes(state: &mut MockState) -> Vec<(String, Presence)> {
+        state
+            .pending
+            .drain(..)
+            .filter_map(|event| match event {
+                BackendEvent::PresenceChanged { user, presence } => Some((user, presence)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn idle_users_decay_to_away_then_offline() {
+        let mut state = MockState::default();
+        state.update_presence(0, "Alice");
+        assert_eq!(presence_changes(&mut state), [("Alice".to_string(), Presence::Online)]);
+
+        state.update_presence(AWAY_AFTER_TICKS - 1, "Bob");
+        assert_eq!(presence_changes(&mut state), [("Bob".to_string(), Presence::Online)]);
+
+        state.update_presence(AWAY_AFTER_TICKS, "Bob");
+        assert_eq!(presence_changes(&mut state), [("Alice".to_string(), Presence::Away)]);
+
+        state.update_presence(OFFLINE_AFTER_TICKS, "Bob");
+        assert_eq!(presence_changes(&mut state), [("Alice".to_string(), Presence::Offline)]);
+
+        state.update_presence(OFFLINE_AFTER_TICKS + 1, "Alice");
+        assert_eq!(presence_changes(&mut state), [("Alice".to_string(), Presence::Online)]);
+    }
+}
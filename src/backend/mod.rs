@@ -0,0 +1,80 @@
+mod mock;
+mod outbox;
+mod systems;
+
+pub use mock::MockBackend;
+pub use outbox::*;
+pub use systems::*;
+
+use std::{collections::HashMap, fmt};
+
+use chrono::{DateTime, Utc};
+
+use crate::{Chat, ChatMessage, Presence};
+
+/// 每次拉取消息的默认条数
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// 后端有新数据时用来唤醒 UI 的回调
+pub type Waker = Box<dyn Fn() + Send + Sync>;
+
+#[derive(Debug, Clone, Default)]
+pub struct MessagePage {
+    /// 按时间从旧到新排列
+    pub messages: Vec<ChatMessage>,
+    /// 在 `messages` 之前是否还有更早的消息
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum BackendEvent {
+    MessageReceived(ChatMessage),
+    /// `user` 已经读到了 `read_at` 之前的所有消息
+    MessagesRead {
+        chat_id: String,
+        user: String,
+        read_at: DateTime<Utc>,
+    },
+    PresenceChanged {
+        user: String,
+        presence: Presence,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendError {
+    ChatNotFound(String),
+    Unavailable(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::ChatNotFound(id) => write!(f, "chat not found: {}", id),
+            BackendError::Unavailable(reason) => write!(f, "backend unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+/// 聊天数据来源，UI 只通过它读取和发送消息
+pub trait ChatBackend: Send + Sync {
+    fn list_chats(&self) -> Vec<Chat>;
+
+    fn unread_counts(&self) -> HashMap<String, i32>;
+
+    /// 拉取 `before`（消息 id）之前的最多 `limit` 条消息，`before` 为空时从最新一条开始
+    fn fetch_messages(&self, chat_id: &str, before: Option<&str>, limit: usize) -> MessagePage;
+
+    /// 发送成功后返回服务端确认过的消息
+    fn send_message(&mut self, message: ChatMessage) -> Result<ChatMessage, BackendError>;
+
+    fn mark_read(&mut self, chat_id: &str);
+
+    /// 开始推送更新，每有新事件就调用一次 `waker`
+    fn subscribe(&mut self, waker: Waker);
+
+    /// 取出自上次调用以来收到的事件
+    fn poll_events(&mut self) -> Vec<BackendEvent>;
+}
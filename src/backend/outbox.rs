@@ -0,0 +1,170 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{ChatMessage, DeliveryState};
+
+/// 自动重试的次数上限，超过后标记为失败，等待用户手动重试
+pub const MAX_AUTO_ATTEMPTS: u32 = 5;
+
+const BASE_BACKOFF_SECS: i64 = 2;
+const MAX_BACKOFF_SECS: i64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub message: ChatMessage,
+    pub attempts: u32,
+    pub next_attempt: DateTime<Utc>,
+}
+
+impl OutboxEntry {
+    pub fn client_key(&self) -> &str {
+        &self.message.client_key
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.message.delivery == DeliveryState::Pending && self.next_attempt <= now
+    }
+}
+
+/// 待发送消息队列，按发送顺序保存
+#[derive(Debug, Default)]
+pub struct Outbox {
+    entries: Vec<OutboxEntry>,
+}
+
+impl Outbox {
+    pub fn from_entries(entries: Vec<OutboxEntry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn enqueue(&mut self, message: ChatMessage, now: DateTime<Utc>) -> OutboxEntry {
+        let entry = OutboxEntry {
+            message: ChatMessage {
+                delivery: DeliveryState::Pending,
+                ..message
+            },
+            attempts: 0,
+            next_attempt: now,
+        };
+        self.entries.push(entry.clone());
+        entry
+    }
+
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<OutboxEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_due(now))
+            .cloned()
+            .collect()
+    }
+
+    /// 下一次需要尝试发送的时间
+    pub fn next_attempt(&self) -> Option<DateTime<Utc>> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.message.delivery == DeliveryState::Pending)
+            .map(|entry| entry.next_attempt)
+            .min()
+    }
+
+    pub fn complete(&mut self, client_key: &str) {
+        self.entries.retain(|entry| entry.client_key() != client_key);
+    }
+
+    /// 记录一次失败，按指数退避安排下一次重试，次数用完后标记为失败
+    pub fn fail(&mut self, client_key: &str, now: DateTime<Utc>) -> Option<OutboxEntry> {
+        let entry = self.get_mut(client_key)?;
+        entry.attempts += 1;
+        if entry.attempts >= MAX_AUTO_ATTEMPTS {
+            entry.message.delivery = DeliveryState::Failed;
+        } else {
+            entry.next_attempt = now + backoff(entry.attempts);
+        }
+        Some(entry.clone())
+    }
+
+    /// 用户手动重试，重新开始计数并立即发送
+    pub fn retry(&mut self, client_key: &str, now: DateTime<Utc>) -> Option<OutboxEntry> {
+        let entry = self.get_mut(client_key)?;
+        entry.attempts = 0;
+        entry.next_attempt = now;
+        entry.message.delivery = DeliveryState::Pending;
+        Some(entry.clone())
+    }
+
+    fn get_mut(&mut self, client_key: &str) -> Option<&mut OutboxEntry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.client_key() == client_key)
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    let secs = BASE_BACKOFF_SECS.saturating_mul(1 << attempts.saturating_sub(1).min(16));
+    Duration::seconds(secs.min(MAX_BACKOFF_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(client_key: &str) -> ChatMessage {
+        ChatMessage {
+            client_key: client_key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let schedule: Vec<i64> = (1..=7).map(|n| backoff(n).num_seconds()).collect();
+        assert_eq!(schedule, vec![2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(backoff(u32::MAX).num_seconds(), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn failed_send_is_rescheduled_with_backoff() {
+        let now = Utc::now();
+        let mut outbox = Outbox::default();
+        outbox.enqueue(message("a"), now);
+        assert_eq!(outbox.due(now).len(), 1);
+
+        let entry = outbox.fail("a", now).unwrap();
+        assert_eq!(entry.attempts, 1);
+        assert_eq!(entry.message.delivery, DeliveryState::Pending);
+        assert_eq!(entry.next_attempt, now + Duration::seconds(2));
+        assert!(outbox.due(now).is_empty());
+        assert_eq!(outbox.due(now + Duration::seconds(2)).len(), 1);
+        assert_eq!(outbox.next_attempt(), Some(now + Duration::seconds(2)));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts_until_retried() {
+        let now = Utc::now();
+        let mut outbox = Outbox::default();
+        outbox.enqueue(message("a"), now);
+        for _ in 0..MAX_AUTO_ATTEMPTS {
+            outbox.fail("a", now);
+        }
+        let later = now + Duration::hours(1);
+        assert!(outbox.due(later).is_empty());
+        assert_eq!(outbox.next_attempt(), None);
+
+        let entry = outbox.retry("a", later).unwrap();
+        assert_eq!(entry.attempts, 0);
+        assert_eq!(entry.message.delivery, DeliveryState::Pending);
+        assert_eq!(outbox.due(later).len(), 1);
+    }
+
+    #[test]
+    fn completed_entries_leave_the_queue() {
+        let now = Utc::now();
+        let mut outbox = Outbox::default();
+        outbox.enqueue(message("a"), now);
+        outbox.enqueue(message("b"), now);
+        outbox.complete("a");
+        let due = outbox.due(now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].client_key(), "b");
+        assert!(outbox.fail("a", now).is_none());
+    }
+}
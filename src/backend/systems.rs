@@ -0,0 +1,65 @@
+use bevy::{
+    log::warn,
+    prelude::{NonSend, ResMut},
+    winit::{EventLoopProxy, WakeUp},
+};
+use bevy_egui::EguiContexts;
+use chrono::Utc;
+
+use crate::resources::UiState;
+
+use super::BackendEvent;
+
+/// 订阅后端更新，收到事件时唤醒处于等待状态的事件循环
+pub fn subscribe_backend(mut ui_state: ResMut<UiState>, proxy: NonSend<EventLoopProxy<WakeUp>>) {
+    let proxy = std::sync::Mutex::new(proxy.clone());
+    ui_state.backend.subscribe(Box::new(move || {
+        let _ = proxy.lock().unwrap().send_event(WakeUp);
+    }));
+}
+
+pub fn poll_backend_events(mut ui_state: ResMut<UiState>) {
+    let events = ui_state.backend.poll_events();
+    for event in events {
+        match event {
+            BackendEvent::MessageReceived(message) => {
+                ui_state.append_message(message);
+            }
+            BackendEvent::MessagesRead {
+                chat_id,
+                user,
+                read_at,
+            } => {
+                ui_state.apply_read_receipt(&chat_id, &user, read_at);
+            }
+            BackendEvent::PresenceChanged { user, presence } => {
+                ui_state.chat_data.presence.insert(user, presence);
+            }
+        }
+    }
+}
+
+/// 投递发件箱里到期的消息，失败的按退避时间安排重试
+pub fn process_outbox(mut ui_state: ResMut<UiState>, mut contexts: EguiContexts) {
+    for entry in ui_state.outbox.due(Utc::now()) {
+        let client_key = entry.client_key().to_string();
+        match ui_state.backend.send_message(entry.message) {
+            Ok(sent) => ui_state.complete_delivery(sent),
+            Err(err) => {
+                warn!("failed to deliver message {}: {}", client_key, err);
+                ui_state.fail_delivery(&client_key);
+            }
+        }
+    }
+
+    // 等待重试期间事件循环可能处于休眠，按下一次重试时间唤醒
+    if let Some(next_attempt) = ui_state.outbox.next_attempt() {
+        let delay = (next_attempt - Utc::now()).to_std().unwrap_or_default();
+        contexts.ctx_mut().request_repaint_after(delay);
+    }
+}
+
+/// 加载滚动到顶部时请求的历史消息
+pub fn load_history(mut ui_state: ResMut<UiState>) {
+    ui_state.load_requested_history();
+}
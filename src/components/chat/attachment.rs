@@ -0,0 +1,338 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    log::warn,
+    prelude::{NonSend, Query, Res},
+    tasks::IoTaskPool,
+    winit::WinitWindows,
+};
+use bevy_egui::{
+    egui::{self, Id, ImageSource},
+    EguiContext,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::resources::UiState;
+
+use super::image_viewer::IMAGE_EXTENSIONS;
+
+/// 消息里附带的文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub name: String,
+    /// 字节数
+    pub size: u64,
+    pub mime: String,
+    /// 文件内容的 SHA-256，十六进制
+    pub sha256: String,
+    /// 文件在本机的位置
+    pub path: String,
+}
+
+impl Attachment {
+    /// 读取本地文件，计算大小和哈希
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self {
+            mime: mime_from_name(&name).to_string(),
+            name,
+            size,
+            sha256: hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            path: path.to_string_lossy().into_owned(),
+        })
+    }
+
+    pub fn exists(&self) -> bool {
+        Path::new(&self.path).is_file()
+    }
+
+    pub fn kind(&self) -> FileKind {
+        FileKind::from_mime(&self.mime)
+    }
+
+    pub fn size_label(&self) -> String {
+        format_size(self.size)
+    }
+}
+
+/// 决定文件卡片上的图标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Pdf,
+    Document,
+    Spreadsheet,
+    Diagram,
+}
+
+impl FileKind {
+    pub fn from_mime(mime: &str) -> Self {
+        match mime {
+            "application/pdf" => FileKind::Pdf,
+            "text/csv"
+            | "application/vnd.ms-excel"
+            | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+                FileKind::Spreadsheet
+            }
+            "application/vnd.visio"
+            | "application/vnd.jgraph.mxfile"
+            | "application/x-xmind"
+            | "image/svg+xml" => FileKind::Diagram,
+            _ => FileKind::Document,
+        }
+    }
+
+    pub fn icon(&self) -> ImageSource<'static> {
+        match self {
+            FileKind::Pdf => egui::include_image!("../../../assets/icons/PDF.svg"),
+            FileKind::Document => egui::include_image!("../../../assets/icons/wendang.svg"),
+            FileKind::Spreadsheet => egui::include_image!("../../../assets/icons/biaoge.svg"),
+            FileKind::Diagram => egui::include_image!("../../../assets/icons/liuchengtu.svg"),
+        }
+    }
+}
+
+/// 能在聊天里直接显示的图片格式
+pub fn is_image_mime(mime: &str) -> bool {
+    IMAGE_EXTENSIONS
+        .iter()
+        .any(|extension| mime_from_name(&format!("image.{}", extension)) == mime)
+}
+
+/// 按扩展名猜 MIME 类型
+pub fn mime_from_name(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "csv" => "text/csv",
+        "vsd" | "vsdx" => "application/vnd.visio",
+        "drawio" => "application/vnd.jgraph.mxfile",
+        "xmind" => "application/x-xmind",
+        "svg" => "image/svg+xml",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 需要系统文件对话框的操作。对话框只能在主线程打开，界面里先放进队列，
+/// 由 `run_file_dialogs` 处理
+#[derive(Debug, Clone)]
+pub enum FileDialogRequest {
+    /// 选择文件加到聊天的输入框里
+    Attach { chat_id: String, images_only: bool },
+    SaveAs(Attachment),
+}
+
+/// 队列放在发起请求的窗口的 egui temp data 里
+pub fn request_file_dialog(ctx: &egui::Context, request: FileDialogRequest) {
+    ctx.data_mut(|data| {
+        data.get_temp_mut_or_default::<Vec<FileDialogRequest>>(Id::new("file_dialogs"))
+            .push(request)
+    });
+}
+
+/// 打开各个窗口排队的文件对话框。`WinitWindows` 让这个系统在主线程上运行
+pub fn run_file_dialogs(
+    mut contexts: Query<&mut EguiContext>,
+    ui_state: Res<UiState>,
+    _main_thread: NonSend<WinitWindows>,
+) {
+    for mut context in contexts.iter_mut() {
+        let ctx = context.get_mut().clone();
+        let requests = ctx
+            .data_mut(|data| data.remove_temp::<Vec<FileDialogRequest>>(Id::new("file_dialogs")))
+            .unwrap_or_default();
+        for request in requests {
+            match request {
+                FileDialogRequest::Attach {
+                    chat_id,
+                    images_only,
+                } => {
+                    let mut dialog = rfd::FileDialog::new();
+                    if images_only {
+                        dialog = dialog.add_filter("图片", IMAGE_EXTENSIONS);
+                    }
+                    if let Some(path) = dialog.pick_file() {
+                        ui_state.attachments.load(&chat_id, path, ctx.clone());
+                    }
+                }
+                FileDialogRequest::SaveAs(attachment) => {
+                    let Some(target) = rfd::FileDialog::new()
+                        .set_file_name(&attachment.name)
+                        .save_file()
+                    else {
+                        continue;
+                    };
+                    IoTaskPool::get()
+                        .spawn(async move {
+                            if let Err(err) = std::fs::copy(&attachment.path, &target) {
+                                warn!(
+                                    "failed to save {} to {}: {}",
+                                    attachment.name,
+                                    target.display(),
+                                    err
+                                );
+                            }
+                        })
+                        .detach();
+                }
+            }
+        }
+    }
+}
+
+struct AttachmentJob {
+    chat_id: String,
+    name: String,
+    /// 读完之前为空
+    result: Arc<Mutex<Option<io::Result<Attachment>>>>,
+}
+
+/// 在 IO 线程池里读取附件、计算哈希，读完后由对应聊天的输入框取走
+#[derive(Clone, Default)]
+pub struct AttachmentLoader {
+    jobs: Arc<Mutex<Vec<AttachmentJob>>>,
+}
+
+impl AttachmentLoader {
+    pub fn load(&self, chat_id: &str, path: PathBuf, ctx: egui::Context) {
+        let result = Arc::new(Mutex::new(None));
+        self.jobs.lock().unwrap().push(AttachmentJob {
+            chat_id: chat_id.to_string(),
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            result: result.clone(),
+        });
+        IoTaskPool::get()
+            .spawn(async move {
+                *result.lock().unwrap() = Some(Attachment::from_path(&path));
+                ctx.request_repaint();
+            })
+            .detach();
+    }
+
+    /// 这个聊天还没放进输入框的文件名
+    pub fn loading(&self, chat_id: &str) -> Vec<String> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|job| job.chat_id == chat_id)
+            .map(|job| job.name.clone())
+            .collect()
+    }
+
+    /// 取走这个聊天已经读完的附件，和文件名一起返回
+    pub fn take_finished(&self, chat_id: &str) -> Vec<(String, io::Result<Attachment>)> {
+        let mut finished = Vec::new();
+        self.jobs.lock().unwrap().retain(|job| {
+            if job.chat_id != chat_id {
+                return true;
+            }
+            match job.result.lock().unwrap().take() {
+                Some(result) => {
+                    finished.push((job.name.clone(), result));
+                    false
+                }
+                None => true,
+            }
+        });
+        finished
+    }
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_job(loader: &AttachmentLoader, chat_id: &str, name: &str, done: bool) {
+        let result = done.then(|| Err(io::Error::new(io::ErrorKind::NotFound, name.to_string())));
+        loader.jobs.lock().unwrap().push(AttachmentJob {
+            chat_id: chat_id.to_string(),
+            name: name.to_string(),
+            result: Arc::new(Mutex::new(result)),
+        });
+    }
+
+    #[test]
+    fn take_finished_keeps_pending_and_other_chats() {
+        let loader = AttachmentLoader::default();
+        push_job(&loader, "a", "done.png", true);
+        push_job(&loader, "a", "pending.zip", false);
+        push_job(&loader, "b", "other.txt", true);
+
+        let finished = loader.take_finished("a");
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0, "done.png");
+        assert_eq!(loader.loading("a"), vec!["pending.zip".to_string()]);
+        assert_eq!(loader.loading("b"), vec!["other.txt".to_string()]);
+    }
+
+    #[test]
+    fn only_displayable_images_count_as_images() {
+        assert!(is_image_mime("image/png"));
+        assert!(is_image_mime("image/webp"));
+        assert!(!is_image_mime("image/svg+xml"));
+        assert!(!is_image_mime("application/pdf"));
+    }
+
+    #[test]
+    fn formats_sizes_with_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+    }
+}
@@ -1,14 +1,30 @@
-use bevy::prelude::ResMut;
+use bevy::{log::warn, prelude::ResMut};
 use bevy_egui::egui::{
     self, vec2, Button, Color32, Frame, Key, Label, Margin, RichText, Rounding, ScrollArea, Sense,
-    TextEdit, Ui, Vec2,
+    Spinner, Stroke, TextEdit, Ui, Vec2,
 };
-use chrono::Local;
+use chrono::{DateTime, Local, Utc};
 
-use crate::resources::{NotificationTheme, UiState};
+use crate::resources::{HistoryState, NotificationTheme, UiState};
 
+use super::code_highlight::{language_label, CODE_LANGUAGES};
+use super::image_viewer::file_name;
 use super::ChatMainView;
-use super::{ChatMessage, ToolbarAction};
+use super::{format_day_label, format_hover_time, format_time};
+use super::{
+    is_image_mime, request_file_dialog, ChatMessage, ChatType, FileDialogRequest, DeliveryState, Lightbox, MessageAction, MessageContent,
+    MessageType, ReadReceipt, Segment, TimelineRow, ToolbarAction, CURRENT_USER,
+};
+
+/// 距离顶部多近时开始加载更早的消息
+const HISTORY_PRELOAD_MARGIN: f32 = 40.0;
+
+/// 同一帧里所有消息共用的渲染参数
+struct MessageFrame<'a> {
+    now: DateTime<Local>,
+    /// 聊天内搜索的命中词
+    highlights: &'a [String],
+}
 
 impl ChatMainView {
     pub fn render_message_content(
@@ -17,11 +33,46 @@ impl ChatMainView {
         ui_state: &mut ResMut<UiState>,
         theme: &mut ResMut<NotificationTheme>,
     ) {
+        if ui_state.chat_search.open {
+            self.render_search_bar(ui, ui_state, theme);
+        }
         self.render_messages(ui, ui_state, theme);
         ui.separator();
         self.render_input_area(ui, ui_state, theme);
     }
 
+    /// 聊天内搜索：输入框、当前是第几条命中、上一条/下一条
+    fn render_search_bar(&self, ui: &mut Ui, ui_state: &mut UiState, theme: &NotificationTheme) {
+        ui.horizontal(|ui| {
+            ui.add_space(10.0);
+            let response = ui.add(
+                TextEdit::singleline(&mut ui_state.chat_search.text)
+                    .desired_width(240.0)
+                    .hint_text("搜索会话记录")
+                    .text_color(theme.text_styles.chat_message.color),
+            );
+            ui_state.refresh_chat_search();
+
+            ui.label(
+                RichText::new(ui_state.chat_search.position_label())
+                    .size(12.0)
+                    .color(theme.text_styles.chat_time.color),
+            );
+            // 回车跳到更早的一条
+            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if ui.add(Button::new("⬆").frame(false)).on_hover_text("上一条").clicked() || enter {
+                ui_state.step_chat_search(true);
+            }
+            if ui.add(Button::new("⬇").frame(false)).on_hover_text("下一条").clicked() {
+                ui_state.step_chat_search(false);
+            }
+            if ui.add(Button::new("✖").frame(false)).on_hover_text("关闭").clicked() {
+                ui_state.toggle_chat_search();
+            }
+        });
+        ui.separator();
+    }
+
     fn render_messages(
         &self,
         ui: &mut Ui,
@@ -30,69 +81,247 @@ impl ChatMainView {
     ) {
         let available_height = ui.available_height();
         let chat_area_height = available_height - 100.0;
-        let mut last_date: Option<String> = None;
-        let mut last_sender: Option<(String, String)> = None;
-        ScrollArea::vertical()
+        let mut action = None;
+        let mut reached_top = false;
+
+        let UiState {
+            chat_data,
+            timeline,
+            topics,
+            select_chat_id,
+            focused_message,
+            chat_search,
+            ..
+        } = &mut **ui_state;
+        let frame = MessageFrame {
+            now: Local::now(),
+            highlights: if chat_search.open {
+                &chat_search.terms
+            } else {
+                &[]
+            },
+        };
+        let messages = chat_data.get_message_for_chat(select_chat_id);
+        let history = chat_data.history_state(select_chat_id);
+        timeline.update(select_chat_id, messages, chat_data.revision(select_chat_id));
+
+        let is_topic_group = chat_data
+            .chats
+            .iter()
+            .any(|chat| chat.id == *select_chat_id && chat.chat_type == ChatType::TopicGroup);
+        if is_topic_group {
+            topics.update(select_chat_id, messages, chat_data.revision(select_chat_id));
+        }
+
+        // 只布局可视区域内的消息，消息再多帧耗时也基本不变
+        let mut scroll_area = ScrollArea::vertical()
             .auto_shrink([false; 2])
             .stick_to_bottom(true)
-            .max_height(chat_area_height)
-            .show(ui, |ui| {
-                for (_idx, message) in ui_state.messages.iter().enumerate() {
-                    let date = message
-                        .timestamp
-                        .split(' ')
-                        .next()
-                        .unwrap_or("")
-                        .to_string();
-
-                    if last_date.as_ref().map_or(true, |last| last != &date) {
-                        ui.vertical_centered(|ui| {
-                            ui.add_space(5.0);
-                            ui.add(Label::new(
-                                RichText::new(&date)
-                                    .color(theme.text_styles.chat_time.color)
-                                    .font(theme.fonts.timestamp.clone())
-                                    .size(12.0),
-                            ));
-                            ui.add_space(5.0);
-                        });
-                        last_date = Some(date);
+            .max_height(chat_area_height);
+        // 前面插入了更早的消息时，保持当前看到的消息位置不变
+        if let Some(offset) = timeline.scroll_correction() {
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
+        scroll_area.show_viewport(ui, |ui, viewport| {
+            reached_top = viewport.top() <= HISTORY_PRELOAD_MARGIN;
+            timeline.show_rows(ui, viewport, |ui, row| match row {
+                TimelineRow::Header => {
+                    self.render_history_state(ui, history, theme);
+                }
+                TimelineRow::DaySeparator { index } => {
+                    self.render_day_separator(ui, &messages[index], &frame.now, theme);
+                }
+                TimelineRow::Message { index, show_avatar } => {
+                    let message = &messages[index];
+                    // 只有自己发出且已送达的消息才显示已读情况
+                    let receipt = (message.is_own()
+                        && message.delivery == DeliveryState::Sent)
+                        .then(|| chat_data.read_receipt(&message.chat_id, message));
+
+                    let inner = ui.scope(|ui| {
+                        if !is_topic_group {
+                            return self.render_message(
+                                ui,
+                                message,
+                                receipt.as_ref(),
+                                show_avatar,
+                                &frame,
+                                theme,
+                            );
+                        }
+                        match &message.reply_to {
+                            Some(root) => {
+                                let root = topics.position(root).map(|index| &messages[index]);
+                                self.render_topic_reply(
+                                    ui,
+                                    message,
+                                    root,
+                                    receipt.as_ref(),
+                                    &frame,
+                                    theme,
+                                )
+                            }
+                            None => {
+                                self.render_topic(
+                                    ui,
+                                    message,
+                                    topics.replies(&message.id),
+                                    receipt.as_ref(),
+                                    &frame,
+                                    theme,
+                                )
+                            }
+                        }
+                    });
+                    if let Some(new_action) = inner.inner {
+                        action = Some(new_action);
+                    }
+                    // 从搜索结果跳过来的消息加一个边框
+                    if focused_message.as_deref() == Some(message.id.as_str()) {
+                        ui.painter().rect_stroke(
+                            inner.response.rect,
+                            Rounding::same(8.0),
+                            Stroke::new(1.5, theme.current_colors().accent),
+                        );
                     }
+                }
+            });
+        });
 
-                    let minute = message
-                        .timestamp
-                        .split(' ')
-                        .nth(1)
-                        .and_then(|t| t.rsplitn(2, ':').last())
-                        .unwrap_or("")
-                        .to_string();
+        if reached_top && history == HistoryState::Idle {
+            let chat_id = ui_state.select_chat_id.clone();
+            ui_state.request_older_messages(&chat_id);
+            ui.ctx().request_repaint();
+        }
 
-                    let show_avatar = last_sender.as_ref().map_or(true, |(sender, min)| {
-                        &message.sender != sender || &minute != min
-                    });
+        match action {
+            Some(MessageAction::Retry { client_key }) => ui_state.retry_message(&client_key),
+            Some(MessageAction::Reply { message_id }) => ui_state.replying_to = Some(message_id),
+            Some(MessageAction::OpenImage { message_id, path }) => {
+                ui_state.lightbox = Some(Lightbox::new(&message_id, &path));
+            }
+            None => {}
+        }
+    }
 
-                    if show_avatar {
-                        last_sender = Some((message.sender.clone(), minute.clone()));
-                    }
+    /// 话题群里的一条话题，下面是回复数和回复按钮
+    fn render_topic(
+        &self,
+        ui: &mut Ui,
+        message: &ChatMessage,
+        replies: usize,
+        receipt: Option<&ReadReceipt>,
+        frame: &MessageFrame,
+        theme: &NotificationTheme,
+    ) -> Option<MessageAction> {
+        let mut action = self.render_message(ui, message, receipt, true, frame, theme);
+        ui.horizontal(|ui| {
+            ui.add_space(70.0);
+            if replies > 0 {
+                ui.label(
+                    RichText::new(format!("{} 条回复", replies))
+                        .size(12.0)
+                        .color(theme.text_styles.chat_time.color),
+                );
+            }
+            let reply = ui.add(
+                Button::new(
+                    RichText::new("回复")
+                        .size(12.0)
+                        .color(theme.current_colors().accent),
+                )
+                .frame(false),
+            );
+            if reply.clicked() {
+                action = Some(MessageAction::Reply {
+                    message_id: message.id.clone(),
+                });
+            }
+        });
+        action
+    }
 
-                    self.render_message(ui, message, show_avatar, theme);
+    /// 话题的回复，缩进显示并引用所属话题
+    fn render_topic_reply(
+        &self,
+        ui: &mut Ui,
+        message: &ChatMessage,
+        root: Option<&ChatMessage>,
+        receipt: Option<&ReadReceipt>,
+        frame: &MessageFrame,
+        theme: &NotificationTheme,
+    ) -> Option<MessageAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.add_space(40.0);
+            ui.vertical(|ui| {
+                if let Some(root) = root {
+                    ui.horizontal(|ui| {
+                        ui.add_space(30.0);
+                        ui.label(
+                            RichText::new(format!("回复 {}: {}", root.sender, root.preview()))
+                                .size(12.0)
+                                .color(theme.text_styles.chat_time.color),
+                        );
+                    });
                 }
+                action = self.render_message(ui, message, receipt, true, frame, theme);
             });
+        });
+        action
+    }
+
+    /// 列表顶部：加载中显示转圈，没有更早的消息时显示会话开头
+    fn render_history_state(&self, ui: &mut Ui, history: HistoryState, theme: &NotificationTheme) {
+        ui.vertical_centered(|ui| {
+            ui.add_space(8.0);
+            match history {
+                HistoryState::Idle => {}
+                HistoryState::Loading => {
+                    ui.add(Spinner::new().size(16.0).color(theme.text_styles.chat_time.color));
+                }
+                HistoryState::Exhausted => {
+                    ui.label(
+                        RichText::new("已经是会话的开头了")
+                            .color(theme.text_styles.chat_time.color)
+                            .size(12.0),
+                    );
+                }
+            }
+            ui.add_space(8.0);
+        });
+    }
+
+    fn render_day_separator(
+        &self,
+        ui: &mut Ui,
+        message: &ChatMessage,
+        now: &DateTime<Local>,
+        theme: &NotificationTheme,
+    ) {
+        ui.vertical_centered(|ui| {
+            ui.add_space(5.0);
+            ui.add(Label::new(
+                RichText::new(format_day_label(&message.timestamp, now))
+                    .color(theme.text_styles.chat_time.color)
+                    .font(theme.fonts.timestamp.clone())
+                    .size(12.0),
+            ));
+            ui.add_space(5.0);
+        });
     }
 
     fn render_message(
         &self,
         ui: &mut Ui,
         message: &ChatMessage,
+        receipt: Option<&ReadReceipt>,
         show_avatar: bool,
+        frame: &MessageFrame,
         theme: &NotificationTheme,
-    ) {
-        let parts: Vec<&str> = message.timestamp.split(' ').collect();
-        let (date, time) = if parts.len() == 2 {
-            (parts[0], parts[1])
-        } else {
-            (message.timestamp.as_str(), "xxx")
-        };
+    ) -> Option<MessageAction> {
+        let time = format_time(&message.timestamp);
+        let mut action = None;
 
         Frame::none().show(ui, |ui| {
             let response =
@@ -117,7 +346,7 @@ impl ChatMainView {
                         ui.add_space(5.0);
                         ui.add(
                             Button::new(
-                                RichText::new(time)
+                                RichText::new(&time)
                                     .color(if is_hovered && !show_avatar {
                                         theme.text_styles.chat_time.color
                                     } else {
@@ -142,11 +371,7 @@ impl ChatMainView {
                                     .strong(),
                             );
                             if is_hovered {
-                                let display_time = format!(
-                                    "{} {}",
-                                    date.split('.').skip(1).collect::<Vec<_>>().join("."),
-                                    time
-                                );
+                                let display_time = format_hover_time(&message.timestamp, &frame.now);
                                 ui.label(
                                     RichText::new(&display_time)
                                         .color(theme.text_styles.chat_time.color)
@@ -157,26 +382,152 @@ impl ChatMainView {
                         });
                     }
 
-                    // 消息框
-                    Frame::none()
-                        .fill(Color32::from_rgba_unmultiplied(0x24, 0x24, 0x24, 245))
-                        .rounding(Rounding::same(8.0))
-                        .inner_margin(Margin::same(8.0))
-                        .show(ui, |ui| {
-                            ui.with_layout(
-                                egui::Layout::left_to_right(egui::Align::LEFT).with_main_wrap(true),
-                                |ui| {
-                                    if let Some(renderer) =
-                                        self.message_renderers.get(&message.message_type)
+                    ui.horizontal(|ui| {
+                        // 消息框
+                        Frame::none()
+                            .fill(Color32::from_rgba_unmultiplied(0x24, 0x24, 0x24, 245))
+                            .rounding(Rounding::same(8.0))
+                            .inner_margin(Margin::same(8.0))
+                            .show(ui, |ui| {
+                                ui.vertical(|ui| {
+                                    if let Some(open) =
+                                        self.render_segments(ui, message, frame.highlights, theme)
                                     {
-                                        renderer.render(ui, message, &self.style, theme);
+                                        action = Some(open);
                                     }
-                                },
-                            );
-                        });
+                                });
+                            });
+                        if let Some(retry) = self.render_delivery_state(ui, message, theme) {
+                            action = Some(retry);
+                        }
+                        if let Some(receipt) = receipt {
+                            self.render_read_receipt(ui, message, receipt, theme);
+                        }
+                    });
                 });
             });
         });
+
+        action
+    }
+
+    /// 按段渲染消息内容，每组段交给对应类型的 `MessageRenderer`，点击图片打开大图
+    fn render_segments(
+        &self,
+        ui: &mut Ui,
+        message: &ChatMessage,
+        highlights: &[String],
+        theme: &NotificationTheme,
+    ) -> Option<MessageAction> {
+        let mut action = None;
+        for segments in message.content.runs() {
+            let segment = &segments[0];
+            let Some(renderer) = self.message_renderers.get(&segment.message_type()) else {
+                continue;
+            };
+            let response = ui
+                .scope(|ui| {
+                    renderer.render_highlighted(
+                        ui,
+                        message,
+                        segments,
+                        &self.style,
+                        theme,
+                        highlights,
+                    );
+                })
+                .response;
+            if let Segment::Image { path } = segment {
+                if response
+                    .interact(Sense::click())
+                    .on_hover_cursor(egui::CursorIcon::ZoomIn)
+                    .clicked()
+                {
+                    action = Some(MessageAction::OpenImage {
+                        message_id: message.id.clone(),
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+        action
+    }
+
+    /// 发送中显示转圈，发送失败显示红色的重试按钮
+    fn render_delivery_state(
+        &self,
+        ui: &mut Ui,
+        message: &ChatMessage,
+        theme: &NotificationTheme,
+    ) -> Option<MessageAction> {
+        match message.delivery {
+            DeliveryState::Sent => None,
+            DeliveryState::Pending => {
+                ui.add(Spinner::new().size(12.0).color(theme.text_styles.chat_time.color));
+                None
+            }
+            DeliveryState::Failed => {
+                let retry = ui
+                    .add(
+                        Button::new(
+                            RichText::new("🔄")
+                                .color(theme.text_styles.chat_unread.color)
+                                .size(14.0),
+                        )
+                        .frame(false),
+                    )
+                    .on_hover_text("发送失败，点击重试");
+                retry.clicked().then(|| MessageAction::Retry {
+                    client_key: message.client_key.clone(),
+                })
+            }
+        }
+    }
+
+    /// 气泡旁的已读状态，点击后列出已读的成员
+    fn render_read_receipt(
+        &self,
+        ui: &mut Ui,
+        message: &ChatMessage,
+        receipt: &ReadReceipt,
+        theme: &NotificationTheme,
+    ) {
+        let color = if receipt.is_all_read() {
+            theme.text_styles.chat_time.color
+        } else {
+            theme.current_colors().accent
+        };
+        let response = ui.add(
+            Button::new(RichText::new(receipt.label()).color(color).size(12.0)).frame(false),
+        );
+        let popup_id = ui.make_persistent_id(("read_receipt", &message.id));
+        if response.clicked() {
+            ui.memory_mut(|mem| mem.toggle_popup(popup_id));
+        }
+        egui::popup_below_widget(
+            ui,
+            popup_id,
+            &response,
+            egui::PopupCloseBehavior::CloseOnClickOutside,
+            |ui| {
+                ui.set_min_width(140.0);
+                ui.label(
+                    RichText::new(format!("已读 {} 人", receipt.read_by.len()))
+                        .color(theme.text_styles.chat_title.color)
+                        .strong(),
+                );
+                for user in &receipt.read_by {
+                    ui.label(RichText::new(user).color(theme.text_styles.chat_message.color));
+                }
+                if !receipt.is_all_read() {
+                    ui.separator();
+                    ui.label(
+                        RichText::new(format!("未读 {} 人", receipt.unread_count))
+                            .color(theme.text_styles.chat_time.color),
+                    );
+                }
+            },
+        );
     }
 
     fn render_input_area(
@@ -185,8 +536,21 @@ impl ChatMainView {
         ui_state: &mut ResMut<UiState>,
         theme: &mut ResMut<NotificationTheme>,
     ) {
+        // 公告频道只能看，不能发
+        if ui_state.current_chat_type().is_read_only() {
+            ui.vertical_centered(|ui| {
+                ui.add_space(20.0);
+                ui.label(
+                    RichText::new("仅频道管理员可以发布消息")
+                        .color(theme.text_styles.chat_time.color),
+                );
+            });
+            return;
+        }
         Frame::none().outer_margin(vec2(1.0, 1.0)).show(ui, |ui| {
             ui.vertical(|ui| {
+                self.render_reply_bar(ui, ui_state, theme);
+                self.render_draft_bar(ui, ui_state, theme);
                 self.render_toolbar(ui, ui_state);
                 if ui_state.show_emoji_picker {
                     // TODO: render emoji picker
@@ -196,6 +560,29 @@ impl ChatMainView {
         });
     }
 
+    /// 正在回复的话题，可以取消
+    fn render_reply_bar(&self, ui: &mut Ui, ui_state: &mut UiState, theme: &NotificationTheme) {
+        let Some(root) = ui_state.replying_to.as_ref().and_then(|id| {
+            ui_state
+                .current_messages()
+                .iter()
+                .find(|message| message.id == *id)
+        }) else {
+            return;
+        };
+        let text = format!("回复 {}: {}", root.sender, root.preview());
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new(text)
+                    .size(12.0)
+                    .color(theme.text_styles.chat_time.color),
+            );
+            if ui.add(Button::new("✖").frame(false)).on_hover_text("取消回复").clicked() {
+                ui_state.replying_to = None;
+            }
+        });
+    }
+
     fn render_toolbar(&self, ui: &mut Ui, ui_state: &mut UiState) {
         ui.horizontal(|ui| {
             ui.spacing_mut().item_spacing.x = 8.0;
@@ -213,6 +600,8 @@ impl ChatMainView {
                         ToolbarAction::SetMessageType(msg_type) => {
                             ui_state.current_message_type = msg_type.clone();
                         }
+                        ToolbarAction::AttachFile => attach_file(ui, ui_state, false),
+                        ToolbarAction::AttachImage => attach_file(ui, ui_state, true),
                         ToolbarAction::None => {}
                     }
                 }
@@ -222,6 +611,19 @@ impl ChatMainView {
                     });
                 }
             }
+            if ui_state.current_message_type == MessageType::Code {
+                egui::ComboBox::from_id_salt("code_language")
+                    .selected_text(language_label(&ui_state.code_language))
+                    .show_ui(ui, |ui| {
+                        for (label, token) in CODE_LANGUAGES {
+                            ui.selectable_value(
+                                &mut ui_state.code_language,
+                                token.to_string(),
+                                *label,
+                            );
+                        }
+                    });
+            }
         });
     }
 
@@ -240,40 +642,132 @@ impl ChatMainView {
             .outer_margin(vec2(4.0, 4.0))
             .inner_margin(vec2(4.0, 4.0));
 
+        let hint = match ui_state.current_chat_type() {
+            ChatType::Direct | ChatType::Bot => format!("发送给 {}", ui_state.current_chat_name()),
+            ChatType::TopicGroup if ui_state.replying_to.is_some() => "回复话题...".to_string(),
+            ChatType::TopicGroup => "发布新话题...".to_string(),
+            ChatType::Group | ChatType::Announcement => "输入消息...".to_string(),
+        };
         frame.show(ui, |ui| {
             let text_edit = TextEdit::multiline(&mut ui_state.input_text)
                 .desired_width(ui.available_width())
                 .desired_rows(1)
                 .min_size(vec2(0.0, 30.0))
-                .hint_text(RichText::new("输入消息...").color(theme.text_styles.chat_message.color))
+                .hint_text(RichText::new(hint).color(theme.text_styles.chat_message.color))
                 .text_color(theme.text_styles.chat_message.color)
                 .frame(false);
         
             let _response = ui.add(text_edit);
             let enter_pressed = ui.input(|i| i.key_pressed(Key::Enter) && !i.modifiers.shift);
 
-            if enter_pressed && !ui_state.input_text.is_empty() {
+            let has_content =
+                !ui_state.input_text.trim().is_empty() || !ui_state.draft_segments.is_empty();
+            if enter_pressed && has_content {
                 self.send_message(ui_state);
             }
         });
     }
 
     fn send_message(&self, ui_state: &mut UiState) {
-        let now = Local::now().format("%Y.%m.%d %H:%M:%S").to_string();
-
-        let trimmed_text = ui_state.input_text.trim().to_string();
-
-        if !trimmed_text.is_empty() {
-            ui_state.messages.push(ChatMessage {
-                id: format!("msg_{}", now),
-                chat_id: ui_state.select_chat_id.clone(),
-                sender: "You".to_string(),
-                avatar: "Y".to_string(),
-                content: trimmed_text,
-                timestamp: now,
-                message_type: ui_state.current_message_type.clone(),
-            });
+        // 先放附件，输入的文字作为说明跟在后面
+        let mut segments = std::mem::take(&mut ui_state.draft_segments);
+        let input = ui_state.input_text.trim();
+        if !input.is_empty() {
+            match ui_state.current_message_type {
+                MessageType::Code => segments.push(Segment::Code {
+                    language: Some(ui_state.code_language.clone()),
+                    code: input.to_string(),
+                }),
+                _ => segments.extend(MessageContent::parse(input).segments),
+            }
         }
         ui_state.input_text.clear();
+        if segments.is_empty() {
+            return;
+        }
+
+        let content = MessageContent::new(segments);
+        let message = ChatMessage {
+            id: ChatMessage::generate_id(),
+            client_key: ChatMessage::generate_id(),
+            chat_id: ui_state.select_chat_id.clone(),
+            sender: CURRENT_USER.to_string(),
+            avatar: "Y".to_string(),
+            message_type: content.message_type(),
+            content,
+            timestamp: Utc::now(),
+            delivery: DeliveryState::Pending,
+            reply_to: ui_state.replying_to.take(),
+        };
+        // 先本地回显，由发件箱负责投递，服务端确认后用 client_key 对上并替换
+        ui_state.queue_message(message);
     }
+
+    /// 待发送的图片和文件，可以移除。读完的附件在这里放进输入框，图片直接显示，其他文件显示成卡片
+    fn render_draft_bar(&self, ui: &mut Ui, ui_state: &mut UiState, theme: &NotificationTheme) {
+        for (name, result) in ui_state.attachments.take_finished(&ui_state.select_chat_id) {
+            match result {
+                Ok(attachment) if is_image_mime(&attachment.mime) => {
+                    ui_state.draft_segments.push(Segment::Image {
+                        path: attachment.path,
+                    });
+                }
+                Ok(attachment) => ui_state.draft_segments.push(Segment::File { attachment }),
+                Err(err) => warn!("failed to read attachment {}: {}", name, err),
+            }
+        }
+        let loading = ui_state.attachments.loading(&ui_state.select_chat_id);
+        if ui_state.draft_segments.is_empty() && loading.is_empty() {
+            return;
+        }
+        let mut removed = None;
+        ui.horizontal_wrapped(|ui| {
+            for name in &loading {
+                ui.spinner();
+                ui.label(
+                    RichText::new(name)
+                        .size(12.0)
+                        .color(theme.text_styles.chat_time.color),
+                );
+            }
+            for (index, segment) in ui_state.draft_segments.iter().enumerate() {
+                let label = match segment {
+                    Segment::Image { path } => format!("🖼 {}", file_name(path)),
+                    Segment::File { attachment } => {
+                        format!("📎 {} ({})", attachment.name, attachment.size_label())
+                    }
+                    other => other.plain_text(),
+                };
+                Frame::none()
+                    .stroke(Stroke::new(1.0, theme.current_colors().border))
+                    .rounding(Rounding::same(4.0))
+                    .inner_margin(Margin::symmetric(6.0, 2.0))
+                    .show(ui, |ui| {
+                        ui.label(
+                            RichText::new(label)
+                                .size(12.0)
+                                .color(theme.text_styles.chat_message.color),
+                        );
+                        if ui.add(Button::new("✖").frame(false)).clicked() {
+                            removed = Some(index);
+                        }
+                    });
+            }
+        });
+        if let Some(index) = removed {
+            ui_state.draft_segments.remove(index);
+        }
+    }
+}
+
+/// 选择本地文件加到待发送的内容里，文件对话框和读取都不在这一帧里进行
+fn attach_file(ui: &Ui, ui_state: &UiState, images_only: bool) {
+    request_file_dialog(
+        ui.ctx(),
+        FileDialogRequest::Attach {
+            chat_id: ui_state.select_chat_id.clone(),
+            images_only,
+        },
+    );
 }
+
@@ -1,6 +1,6 @@
 use bevy_egui::egui::{self, Color32, FontId, Margin, Rounding};
 
-use crate::{resources::NotificationTheme, ChatMessage, MessageType};
+use crate::{resources::NotificationTheme, ChatMessage, MessageType, Segment};
 
 #[derive(Clone)]
 pub struct ChatMainStyle {
@@ -20,14 +20,39 @@ pub struct ChatFoots {
     pub timestamp: FontId,
 }
 
+/// 渲染消息内容里的一组段：连续的行内段，或者单独的一段图片、文件、代码
 pub trait MessageRenderer {
     fn render(
         &self,
         ui: &mut egui::Ui,
         message: &ChatMessage,
+        segments: &[Segment],
         style: &ChatMainStyle,
         theme: &NotificationTheme,
     );
+
+    /// 聊天内搜索时调用，高亮 `terms` 里的词，默认不高亮
+    fn render_highlighted(
+        &self,
+        ui: &mut egui::Ui,
+        message: &ChatMessage,
+        segments: &[Segment],
+        style: &ChatMainStyle,
+        theme: &NotificationTheme,
+        _terms: &[String],
+    ) {
+        self.render(ui, message, segments, style, theme);
+    }
+}
+
+/// 消息气泡上触发、需要修改 `UiState` 的操作
+#[derive(Clone, Debug)]
+pub enum MessageAction {
+    Retry { client_key: String },
+    /// 在话题群里回复这条话题
+    Reply { message_id: String },
+    /// 查看图片大图
+    OpenImage { message_id: String, path: String },
 }
 
 #[derive(Clone)]
@@ -41,5 +66,19 @@ pub struct ToolBarButton {
 pub enum ToolbarAction {
     ToggleEmoji,
     SetMessageType(MessageType),
+    /// 选择本地文件发送
+    AttachFile,
+    /// 只能选图片的文件选择框
+    AttachImage,
     None,
 }
+
+/// 聊天标题栏右侧的工具按钮
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HeaderAction {
+    SearchHistory,
+    VideoMeeting,
+    AddMember,
+    Calendar,
+    Members,
+}
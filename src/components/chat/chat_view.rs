@@ -1,15 +1,17 @@
 use bevy::prelude::ResMut;
 use bevy_egui::egui::{
-    self, popup_below_widget, Align, Button, Color32, Context, Frame, Id, InnerResponse, Layout,
-    PopupCloseBehavior, Response, RichText, ScrollArea, SidePanel, Ui, Vec2,
+    self, popup_below_widget, Align, Button, CentralPanel, Color32, Context, Frame, Id,
+    InnerResponse, Layout, PopupCloseBehavior, Response, RichText, ScrollArea, Sense, SidePanel,
+    Ui, Vec2,
 };
 use std::collections::HashMap;
 
 use crate::resources::{ChatTab, NotificationTheme, UiState};
 
 use super::{
-    Chat, ChatMainStyle, ChatType, CodeMessageRenderer, FileMessageRenderer, ImageMessageRenderer,
-    MessageRenderer, MessageType, TextMessageRenderer, ToolBarButton, ToolbarAction,
+    render_lightbox, Chat, ChatMainStyle, ChatType, CodeMessageRenderer, FileMessageRenderer,
+    ImageMessageRenderer, MessageRenderer, MessageType, Presence, TextMessageRenderer,
+    HeaderAction, ToolBarButton, ToolbarAction,
 };
 
 pub struct ChatMainView {
@@ -52,7 +54,12 @@ impl ChatMainView {
             ToolBarButton {
                 icon: "\u{e6a3}",
                 tooltip: "附件",
-                action: ToolbarAction::SetMessageType(MessageType::File),
+                action: ToolbarAction::AttachFile,
+            },
+            ToolBarButton {
+                icon: "🖼",
+                tooltip: "图片",
+                action: ToolbarAction::AttachImage,
             },
             ToolBarButton {
                 icon: "\u{e6a}",
@@ -91,22 +98,40 @@ impl ChatMainView {
             .frame(self.create_frame(ctx, &theme))
             .resizable(false)
             .min_width(600.0)
-            .show(ctx, |ui| {
-                ui.vertical(|ui| {
-                    self.render_header(ui, ui_state, &theme);
-                    ui.separator();
-                    Frame::none().show(ui, |ui| match ui_state.current_tab {
-                        ChatTab::Message => self.render_message_content(ui, ui_state, theme),
-                        ChatTab::Document => self.render_document_content(ui, ui_state, theme),
-                        ChatTab::Announcement => {
-                            self.render_announcement_content(ui, ui_state, theme)
-                        }
-                        ChatTab::Pin => self.render_pin_content(ui, ui_state, theme),
-                        ChatTab::File => self.render_file_content(ui, ui_state, theme),
-                        _ => {}
-                    });
-                });
-            })
+            .show(ctx, |ui| self.render_body(ui, ui_state, theme))
+    }
+
+    /// 独立窗口里占满整个窗口
+    pub fn render_window(
+        &self,
+        ctx: &Context,
+        ui_state: &mut ResMut<UiState>,
+        theme: &mut ResMut<NotificationTheme>,
+    ) -> InnerResponse<()> {
+        CentralPanel::default()
+            .frame(self.create_frame(ctx, &theme))
+            .show(ctx, |ui| self.render_body(ui, ui_state, theme))
+    }
+
+    fn render_body(
+        &self,
+        ui: &mut Ui,
+        ui_state: &mut ResMut<UiState>,
+        theme: &mut ResMut<NotificationTheme>,
+    ) {
+        ui.vertical(|ui| {
+            self.render_header(ui, ui_state, &theme);
+            ui.separator();
+            Frame::none().show(ui, |ui| match ui_state.current_tab {
+                ChatTab::Message => self.render_message_content(ui, ui_state, theme),
+                ChatTab::Document => self.render_document_content(ui, ui_state, theme),
+                ChatTab::Announcement => self.render_announcement_content(ui, ui_state, theme),
+                ChatTab::Pin => self.render_pin_content(ui, ui_state, theme),
+                ChatTab::File => self.render_file_content(ui, ui_state, theme),
+                _ => {}
+            });
+        });
+        render_lightbox(ui.ctx(), ui_state, theme);
     }
     fn render_header(
         &self,
@@ -116,6 +141,7 @@ impl ChatMainView {
     ) {
         let _current_tab = ui_state.current_tab.clone();
         if let Some(chat) = ui_state
+            .chat_data
             .chats
             .iter()
             .find(|c| c.id == ui_state.select_chat_id)
@@ -161,13 +187,20 @@ impl ChatMainView {
         ui_state: &mut ResMut<UiState>,
         theme: &NotificationTheme,
     ) {
+        let presence = ui_state.chat_data.presence_of(&chat.name);
         ui.vertical(|ui| {
-            self.render_chat_header(ui, chat, theme);
+            self.render_chat_header(ui, chat, presence, theme);
             ui.add_space(8.0);
             self.render_tabs(ui, ui_state, theme);
         });
     }
-    fn render_chat_header(&self, ui: &mut Ui, chat: &Chat, theme: &NotificationTheme) {
+    fn render_chat_header(
+        &self,
+        ui: &mut Ui,
+        chat: &Chat,
+        presence: Presence,
+        theme: &NotificationTheme,
+    ) {
         ui.horizontal(|ui| {
             ui.heading(
                 RichText::new(&chat.name)
@@ -175,12 +208,42 @@ impl ChatMainView {
                     .font(theme.fonts.title.clone())
                     .color(theme.text_styles.chat_title.color),
             );
-            self.render_chat_type_indicator(ui, chat, theme);
+            self.render_chat_type_indicator(ui, chat, presence, theme);
+            if chat.notification.is_muted() {
+                ui.label(
+                    RichText::new("🔕")
+                        .size(12.0)
+                        .color(theme.text_styles.chat_time.color),
+                )
+                .on_hover_text(chat.notification.label());
+            }
         });
     }
-    fn render_chat_type_indicator(&self, ui: &mut Ui, chat: &Chat, theme: &NotificationTheme) {
+    fn render_chat_type_indicator(
+        &self,
+        ui: &mut Ui,
+        chat: &Chat,
+        presence: Presence,
+        theme: &NotificationTheme,
+    ) {
+        if let Some(badge) = chat.chat_type.badge() {
+            ui.add_space(5.0);
+            render_type_badge(ui, badge, theme);
+        }
         match chat.chat_type {
-            ChatType::Group => {
+            ChatType::Direct => {
+                ui.add_space(5.0);
+                let (rect, _) = ui.allocate_exact_size(Vec2::splat(8.0), Sense::hover());
+                ui.painter()
+                    .circle_filled(rect.center(), 4.0, presence_color(presence, theme));
+                ui.label(
+                    RichText::new(presence.label())
+                        .size(12.0)
+                        .color(theme.text_styles.chat_time.color),
+                );
+            }
+            ChatType::Bot => {}
+            ChatType::Group | ChatType::TopicGroup | ChatType::Announcement => {
                 ui.add_space(5.0);
                 ui.small(
                     RichText::new("\u{e748}")
@@ -323,11 +386,11 @@ impl ChatMainView {
         }
     }
 
-    fn render_right_toolbar(&self, ui: &mut Ui, _ui_state: &mut ResMut<UiState>) {
+    fn render_right_toolbar(&self, ui: &mut Ui, ui_state: &mut ResMut<UiState>) {
         ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
             ui.add_space(10.0);
             self.render_more_menu(ui);
-            self.render_tool_button(ui);
+            self.render_tool_button(ui, ui_state);
         });
     }
 
@@ -463,19 +526,24 @@ impl ChatMainView {
         }
     }
 
-    fn render_tool_button(&self, ui: &mut Ui) {
-        for (icon, tooltip) in [
-            ("\u{e71a}", "搜索会话记录"),
-            ("\u{e662}", "视频会议"),
-            ("\u{e777}", "添加新成员"),
-            ("\u{eb2b}", "日历"),
-            ("\u{e748}", "群成员"),
+    fn render_tool_button(&self, ui: &mut Ui, ui_state: &mut ResMut<UiState>) {
+        for (icon, tooltip, action) in [
+            ("\u{e71a}", "搜索会话记录", HeaderAction::SearchHistory),
+            ("\u{e662}", "视频会议", HeaderAction::VideoMeeting),
+            ("\u{e777}", "添加新成员", HeaderAction::AddMember),
+            ("\u{eb2b}", "日历", HeaderAction::Calendar),
+            ("\u{e748}", "群成员", HeaderAction::Members),
         ] {
-            let btn = ui.add(Button::new(icon).frame(false));
+            let btn = ui.add(Button::new(icon).frame(false)).on_hover_text(tooltip);
             if btn.clicked() {
-                btn.on_hover_ui(|ui| {
-                    ui.label(tooltip);
-                });
+                match action {
+                    HeaderAction::SearchHistory => ui_state.toggle_chat_search(),
+                    // 还没有实现
+                    HeaderAction::VideoMeeting
+                    | HeaderAction::AddMember
+                    | HeaderAction::Calendar
+                    | HeaderAction::Members => {}
+                }
             }
             ui.add_space(5.0);
         }
@@ -489,3 +557,23 @@ impl ChatMainView {
         self.style.colors.avatar_colors[index]
     }
 }
+
+/// 聊天名旁边的类型标签，例如“机器人”
+pub fn render_type_badge(ui: &mut Ui, text: &str, theme: &NotificationTheme) {
+    let colors = theme.current_colors();
+    Frame::none()
+        .stroke(egui::Stroke::new(1.0, colors.accent))
+        .rounding(3.0)
+        .inner_margin(egui::Margin::symmetric(3.0, 0.0))
+        .show(ui, |ui| {
+            ui.label(RichText::new(text).size(10.0).color(colors.accent));
+        });
+}
+
+pub fn presence_color(presence: Presence, theme: &NotificationTheme) -> Color32 {
+    match presence {
+        Presence::Online => Color32::from_rgb(0x34, 0xc7, 0x59),
+        Presence::Away => Color32::from_rgb(0xff, 0xb0, 0x20),
+        Presence::Offline => theme.text_styles.chat_time.color,
+    }
+}
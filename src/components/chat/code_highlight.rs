@@ -0,0 +1,138 @@
+use std::sync::OnceLock;
+
+use bevy_egui::egui::{
+    self,
+    text::{LayoutJob, TextFormat},
+    util::cache::{ComputerMut, FrameCache},
+    Color32, FontId,
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{self, FontStyle, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// 输入框里可选的语言，值是 syntect 认识的 token
+pub const CODE_LANGUAGES: &[(&str, &str)] = &[
+    ("Plain Text", "txt"),
+    ("Rust", "rs"),
+    ("Python", "py"),
+    ("JavaScript", "js"),
+    ("Go", "go"),
+    ("Java", "java"),
+    ("C++", "cpp"),
+    ("SQL", "sql"),
+    ("JSON", "json"),
+    ("Shell", "sh"),
+];
+
+/// 显示用的语言名，不认识的原样返回
+pub fn language_label(language: &str) -> &str {
+    CODE_LANGUAGES
+        .iter()
+        .find(|(_, token)| token.eq_ignore_ascii_case(language))
+        .map_or(language, |(label, _)| label)
+}
+
+/// 深色和浅色模式用的 syntect 配色
+fn theme_name(dark: bool) -> &'static str {
+    if dark {
+        "base16-ocean.dark"
+    } else {
+        "InspiredGitHub"
+    }
+}
+
+fn to_color32(color: highlighting::Color) -> Color32 {
+    Color32::from_rgb(color.r, color.g, color.b)
+}
+
+/// 配色自带的背景色和文字颜色，代码块画在这个背景上，不受气泡颜色影响
+pub fn code_colors(dark: bool) -> (Color32, Color32) {
+    let (background, foreground) = if dark {
+        (Color32::from_rgb(0x2b, 0x30, 0x3b), Color32::from_rgb(0xc0, 0xc5, 0xce))
+    } else {
+        (Color32::WHITE, Color32::from_rgb(0x32, 0x32, 0x32))
+    };
+    let Some(theme) = highlighter().themes.themes.get(theme_name(dark)) else {
+        return (background, foreground);
+    };
+    (
+        theme.settings.background.map_or(background, to_color32),
+        theme.settings.foreground.map_or(foreground, to_color32),
+    )
+}
+
+struct Highlighter {
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+}
+
+/// 加载语法和配色比较慢，第一次用到时再加载
+fn highlighter() -> &'static Highlighter {
+    static HIGHLIGHTER: OnceLock<Highlighter> = OnceLock::new();
+    HIGHLIGHTER.get_or_init(|| Highlighter {
+        syntaxes: SyntaxSet::load_defaults_newlines(),
+        themes: ThemeSet::load_defaults(),
+    })
+}
+
+impl Highlighter {
+    fn syntax(&self, language: &str, code: &str) -> &SyntaxReference {
+        self.syntaxes
+            .find_syntax_by_token(language)
+            .or_else(|| self.syntaxes.find_syntax_by_first_line(code))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+
+    fn highlight(&self, code: &str, language: &str, dark: bool, font: FontId) -> LayoutJob {
+        let mut job = LayoutJob::default();
+        let Some(theme) = self.themes.themes.get(theme_name(dark)) else {
+            job.append(code, 0.0, TextFormat::simple(font, Color32::GRAY));
+            return job;
+        };
+        let mut lines = HighlightLines::new(self.syntax(language, code), theme);
+        for line in LinesWithEndings::from(code) {
+            let Ok(ranges) = lines.highlight_line(line, &self.syntaxes) else {
+                job.append(line, 0.0, TextFormat::simple(font.clone(), Color32::GRAY));
+                continue;
+            };
+            for (style, text) in ranges {
+                let mut format = TextFormat::simple(font.clone(), to_color32(style.foreground));
+                format.italics = style.font_style.contains(FontStyle::ITALIC);
+                if style.font_style.contains(FontStyle::UNDERLINE) {
+                    format.underline = egui::Stroke::new(1.0, format.color);
+                }
+                job.append(text, 0.0, format);
+            }
+        }
+        job
+    }
+}
+
+#[derive(Default)]
+struct CodeLayouter;
+
+impl ComputerMut<(&str, &str, bool, &FontId), LayoutJob> for CodeLayouter {
+    fn compute(&mut self, (code, language, dark, font): (&str, &str, bool, &FontId)) -> LayoutJob {
+        highlighter().highlight(code, language, dark, font.clone())
+    }
+}
+
+type CodeLayoutCache = FrameCache<LayoutJob, CodeLayouter>;
+
+/// 高亮后的代码，结果按内容缓存
+pub fn highlight_code(
+    ctx: &egui::Context,
+    code: &str,
+    language: &str,
+    dark: bool,
+    font: &FontId,
+) -> LayoutJob {
+    ctx.memory_mut(|mem| {
+        mem.caches
+            .cache::<CodeLayoutCache>()
+            .get((code, language, dark, font))
+    })
+}
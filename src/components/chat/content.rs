@@ -0,0 +1,396 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Attachment, MessageType, CURRENT_USER};
+
+/// 当前的存储格式版本，格式变化时加一并在 `MessageContent::from_storage` 里兼容旧版本
+pub const CONTENT_VERSION: u32 = 1;
+
+/// 消息内容的一段
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Segment {
+    /// 支持 Markdown
+    Text {
+        text: String,
+    },
+    Mention {
+        user: String,
+    },
+    Link {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    Emoji {
+        emoji: String,
+    },
+    /// 本地图片的路径
+    Image {
+        path: String,
+    },
+    File {
+        attachment: Attachment,
+    },
+    Code {
+        #[serde(default)]
+        language: Option<String>,
+        code: String,
+    },
+}
+
+impl Segment {
+    pub fn text(text: impl Into<String>) -> Self {
+        Segment::Text { text: text.into() }
+    }
+
+    /// 文字、提及、链接和表情在同一段落里连续显示
+    pub fn is_inline(&self) -> bool {
+        matches!(
+            self,
+            Segment::Text { .. }
+                | Segment::Mention { .. }
+                | Segment::Link { .. }
+                | Segment::Emoji { .. }
+        )
+    }
+
+    /// 用哪个 `MessageRenderer` 渲染
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            Segment::Image { .. } => MessageType::Images,
+            Segment::File { .. } => MessageType::File,
+            Segment::Code { .. } => MessageType::Code,
+            _ => MessageType::Text,
+        }
+    }
+
+    /// 搜索和摘要用的纯文本
+    pub fn plain_text(&self) -> String {
+        match self {
+            Segment::Text { text } => text.clone(),
+            Segment::Mention { user } => format!("@{}", user),
+            Segment::Link { url, text } => text.clone().unwrap_or_else(|| url.clone()),
+            Segment::Emoji { emoji } => emoji.clone(),
+            Segment::Image { .. } => String::new(),
+            Segment::File { attachment } => attachment.name.clone(),
+            Segment::Code { code, .. } => code.clone(),
+        }
+    }
+
+    /// 摘要里的占位符
+    fn placeholder(&self) -> Option<&'static str> {
+        match self {
+            Segment::Image { .. } => Some("[图片]"),
+            Segment::File { .. } => Some("[文件]"),
+            Segment::Code { .. } => Some("[代码]"),
+            _ => None,
+        }
+    }
+}
+
+/// 一条消息的内容，由多段组成
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageContent {
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredContent {
+    v: u32,
+    segments: Vec<Segment>,
+}
+
+impl MessageContent {
+    pub fn new(segments: Vec<Segment>) -> Self {
+        Self { segments }
+    }
+
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(vec![Segment::text(text)])
+    }
+
+    /// 解析输入框里的文字：```` ``` ```` 围起来的是代码块，`@名字` 是提及，`http(s)://` 开头的是链接
+    pub fn parse(input: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut rest = input;
+        while let Some(start) = rest.find("```") {
+            let after = &rest[start + 3..];
+            let Some(end) = after.find("```") else {
+                break;
+            };
+            push_inline(&mut segments, &rest[..start]);
+            let block = &after[..end];
+            let (language, code) = match block.split_once('\n') {
+                Some((first, code)) if !first.trim().contains(' ') => (first.trim(), code),
+                _ => ("", block),
+            };
+            segments.push(Segment::Code {
+                language: (!language.is_empty()).then(|| language.to_string()),
+                code: code.trim_end_matches('\n').to_string(),
+            });
+            rest = &after[end + 3..];
+        }
+        push_inline(&mut segments, rest);
+        Self::new(segments)
+    }
+
+    /// 保存到数据库的格式：`{"v":1,"segments":[...]}`。
+    pub fn to_storage(&self) -> String {
+        serde_json::to_string(&StoredContent {
+            v: CONTENT_VERSION,
+            segments: self.segments.clone(),
+        })
+        .expect("segments only hold strings and plain structs")
+    }
+
+    /// 不是 JSON 的按纯文本处理，兼容后端直接发来的文字；更新版本写入的内容显示提示
+    pub fn from_storage(raw: &str) -> Self {
+        match serde_json::from_str::<StoredContent>(raw) {
+            Ok(stored) if stored.v <= CONTENT_VERSION => Self::new(stored.segments),
+            Ok(_) => Self::text("[暂不支持的消息，请升级到最新版本查看]"),
+            Err(_) => Self::text(raw),
+        }
+    }
+
+    /// 按出现的内容决定消息类型，搜索的 `has:` 条件和文件列表用到
+    pub fn message_type(&self) -> MessageType {
+        [MessageType::File, MessageType::Images, MessageType::Code]
+            .into_iter()
+            .find(|kind| self.segments.iter().any(|s| s.message_type() == *kind))
+            .unwrap_or_default()
+    }
+
+    /// 连续的行内段合成一组，其他每段单独一组，每组交给一个 `MessageRenderer`
+    pub fn runs(&self) -> Vec<&[Segment]> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for (index, segment) in self.segments.iter().enumerate() {
+            let inline = segment.is_inline();
+            let next_inline = self.segments.get(index + 1).is_some_and(Segment::is_inline);
+            if !(inline && next_inline) {
+                runs.push(&self.segments[start..=index]);
+                start = index + 1;
+            }
+        }
+        runs
+    }
+
+    pub fn plain_text(&self) -> String {
+        let mut text = String::new();
+        for segment in &self.segments {
+            if !segment.is_inline() && !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&segment.plain_text());
+        }
+        text
+    }
+
+    /// 一行摘要，图片、文件和代码用占位符
+    pub fn preview(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        for run in self.runs() {
+            match run[0].placeholder() {
+                Some(placeholder) => parts.push(placeholder.to_string()),
+                None => parts.push(run.iter().map(Segment::plain_text).collect()),
+            }
+        }
+        let preview = parts.join(" ");
+        preview
+            .trim()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    pub fn mentions(&self, user: &str) -> bool {
+        self.segments.iter().any(|segment| match segment {
+            Segment::Mention { user: mentioned } => mentioned == user,
+            // 旧消息和后端发来的纯文本
+            Segment::Text { text } => text_mentions(text, user),
+            _ => false,
+        })
+    }
+
+    pub fn mentions_me(&self) -> bool {
+        self.mentions(CURRENT_USER) || self.mentions("所有人")
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Image { path } => Some(path.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+/// 名字后面紧跟的标点，不算进名字里
+fn ends_name(c: char) -> bool {
+    (c.is_ascii_punctuation() && c != '/') || "，。！？、：；".contains(c)
+}
+
+/// 纯文本里的 `@名字`，后面要是结尾、空白或标点，`@Youssef` 不算 @ 了 `You`
+fn text_mentions(text: &str, user: &str) -> bool {
+    let needle = format!("@{}", user);
+    text.match_indices(&needle).any(|(index, _)| {
+        text[index + needle.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || ends_name(c))
+    })
+}
+
+/// 把一段文字拆成文本、提及和链接
+fn push_inline(segments: &mut Vec<Segment>, text: &str) {
+    let mut plain = String::new();
+    for word in text.split_inclusive(char::is_whitespace) {
+        let trimmed = word.trim_end();
+        let token = trimmed.trim_end_matches(ends_name);
+        let rest = &word[token.len()..];
+        let segment = if let Some(user) = token.strip_prefix('@').filter(|u| !u.is_empty()) {
+            Segment::Mention {
+                user: user.to_string(),
+            }
+        } else if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
+            Segment::Link {
+                url: trimmed.to_string(),
+                text: None,
+            }
+        } else {
+            plain.push_str(word);
+            continue;
+        };
+        if !plain.is_empty() {
+            segments.push(Segment::text(std::mem::take(&mut plain)));
+        }
+        let is_link = matches!(segment, Segment::Link { .. });
+        segments.push(segment);
+        plain.push_str(if is_link {
+            &word[trimmed.len()..]
+        } else {
+            rest
+        });
+    }
+    // 代码块后面的换行不用再显示
+    let plain = if segments.last().is_some_and(|s| !s.is_inline()) {
+        plain.trim_start_matches('\n')
+    } else {
+        plain.as_str()
+    };
+    if !plain.trim().is_empty() {
+        segments.push(Segment::text(plain));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(user: &str) -> Segment {
+        Segment::Mention {
+            user: user.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_splits_code_blocks_from_text() {
+        let content = MessageContent::parse("look:\n```rust\nfn main() {}\n```\nthanks");
+        assert_eq!(
+            content.segments,
+            [
+                Segment::text("look:\n"),
+                Segment::Code {
+                    language: Some("rust".to_string()),
+                    code: "fn main() {}".to_string(),
+                },
+                Segment::text("thanks"),
+            ]
+        );
+        assert_eq!(content.message_type(), MessageType::Code);
+
+        // 没有闭合的围栏按普通文字处理
+        assert_eq!(
+            MessageContent::parse("```not closed").segments,
+            [Segment::text("```not closed")]
+        );
+    }
+
+    #[test]
+    fn push_inline_finds_mentions_and_links() {
+        let mut segments = Vec::new();
+        push_inline(&mut segments, "hi @Ray, see https://example.com/a now");
+        assert_eq!(
+            segments,
+            [
+                Segment::text("hi "),
+                mention("Ray"),
+                Segment::text(", see "),
+                Segment::Link {
+                    url: "https://example.com/a".to_string(),
+                    text: None,
+                },
+                Segment::text(" now"),
+            ]
+        );
+
+        // 单独的 @ 和空白不产生分段
+        let mut segments = Vec::new();
+        push_inline(&mut segments, "@ \n");
+        assert_eq!(segments, [Segment::text("@ \n")]);
+        let mut segments = Vec::new();
+        push_inline(&mut segments, "  ");
+        assert!(segments.is_empty());
+    }
+
+    #[test]
+    fn plain_text_mentions_need_the_whole_name() {
+        let text = |text: &str| MessageContent::text(text);
+        assert!(text("@You").mentions("You"));
+        assert!(text("hi @You, look").mentions("You"));
+        assert!(text("@You\nnext").mentions("You"));
+        assert!(text("@Youssef 你好 @You。").mentions("You"));
+        assert!(!text("@Youssef").mentions("You"));
+        assert!(!text("@You/team").mentions("You"));
+        assert!(!text("You").mentions("You"));
+    }
+
+    #[test]
+    fn runs_group_inline_segments_only() {
+        let content = MessageContent::new(vec![
+            Segment::text("a "),
+            mention("Ray"),
+            Segment::Image {
+                path: "x.png".to_string(),
+            },
+            Segment::Image {
+                path: "y.png".to_string(),
+            },
+            Segment::text("b"),
+        ]);
+        let lengths: Vec<usize> = content.runs().iter().map(|run| run.len()).collect();
+        assert_eq!(lengths, [2, 1, 1, 1]);
+        assert_eq!(content.preview(), "a @Ray [图片] [图片] b");
+    }
+
+    #[test]
+    fn storage_round_trips_and_falls_back_to_text() {
+        let content = MessageContent::parse("hi @Ray\n```\nx\n```");
+        assert_eq!(MessageContent::from_storage(&content.to_storage()), content);
+
+        // 旧的纯文本和后端直接发来的文字
+        assert_eq!(
+            MessageContent::from_storage("plain {not json"),
+            MessageContent::text("plain {not json")
+        );
+        let newer = format!(r#"{{"v":{},"segments":[]}}"#, CONTENT_VERSION + 1);
+        assert_eq!(
+            MessageContent::from_storage(&newer),
+            MessageContent::text("[暂不支持的消息，请升级到最新版本查看]")
+        );
+    }
+}
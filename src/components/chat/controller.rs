@@ -1,5 +1,5 @@
-use super::{Chat, ChatEvent, ChatFilter, ChatListModel};
-use std::collections::HashMap;
+use super::{ChatEvent, ChatFilter, ChatListModel};
+use crate::resources::ChatData;
 
 #[derive(Debug)]
 pub struct ChatListController {
@@ -7,9 +7,9 @@ pub struct ChatListController {
 }
 
 impl ChatListController {
-    pub fn new(chats: &[Chat], selected_id: &str, unread_count: &HashMap<String, i32>) -> Self {
+    pub fn new(chat_data: &ChatData, selected_id: &str, filter: ChatFilter) -> Self {
         Self {
-            model: ChatListModel::new(chats, selected_id, unread_count),
+            model: ChatListModel::new(chat_data, selected_id, filter),
         }
     }
     pub fn handle_click(&mut self, id: String) -> ChatEvent {
@@ -1,5 +1,23 @@
+use super::NotificationLevel;
+
 #[derive(Clone, Debug)]
 pub enum ChatEvent {
   Selected { id: String },
+  /// 右键菜单里的操作
+  Action { id: String, action: ChatAction },
   None,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatAction {
+  TogglePin,
+  /// 有未读时标为已读，否则标为未读
+  ToggleUnread,
+  ToggleFlag,
+  /// 有这个标签就去掉，没有就加上，新建标签也走这里
+  ToggleTag(String),
+  SetNotification(NotificationLevel),
+  ToggleDone,
+  ToggleNavPin,
+  ToggleWindow,
 }
\ No newline at end of file
@@ -0,0 +1,471 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{prelude::ResMut, tasks::AsyncComputeTaskPool};
+use bevy_egui::egui::{
+    self, Color32, ColorImage, Context, Id, Key, Rect, RichText, Sense, TextureHandle,
+    TextureOptions, Vec2,
+};
+use image::{imageops::FilterType, DynamicImage};
+
+use crate::resources::{NotificationTheme, UiState};
+
+/// 选择图片时可选的扩展名
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+/// 聊天里缩略图的最大边长
+pub const THUMBNAIL_SIZE: f32 = 240.0;
+/// 大图解码后的最大边长，避免超大图片占用太多显存
+const FULL_IMAGE_MAX: u32 = 4096;
+/// 纹理缓存最多占用的显存，超出后清掉最久没显示的图片
+const TEXTURE_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+/// 解码失败后隔多久再试，文件可能稍后才下载好
+const RETRY_FAILED_SECONDS: f64 = 5.0;
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 8.0;
+
+#[derive(Clone)]
+pub enum ImageStatus {
+    Loading,
+    Ready {
+        texture: TextureHandle,
+        /// 原图的像素尺寸
+        size: Vec2,
+    },
+    Failed(String),
+}
+
+/// 键是（路径，是否大图）
+type ImageKey = (String, bool);
+
+struct Decoded {
+    key: ImageKey,
+    result: Result<(ColorImage, Vec2), String>,
+}
+
+struct Entry {
+    status: ImageStatus,
+    /// 纹理占用的字节数，加载中和失败的为 0
+    bytes: usize,
+    last_used: u64,
+    /// 解码失败的时间，用 egui 的 `input.time`
+    failed_at: Option<f64>,
+}
+
+/// 按最近使用时间淘汰的纹理表，纹理的总字节数不超过 `budget`
+struct ImageEntries {
+    entries: HashMap<ImageKey, Entry>,
+    bytes: usize,
+    budget: usize,
+    /// 每次访问加一，用来比较先后
+    clock: u64,
+}
+
+impl ImageEntries {
+    fn new(budget: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            bytes: 0,
+            budget,
+            clock: 0,
+        }
+    }
+
+    /// 取出状态，同时记为刚用过。失败超过 `RETRY_FAILED_SECONDS` 的返回 `None`，让调用方重新解码
+    fn get(&mut self, key: &ImageKey, now: f64) -> Option<ImageStatus> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        if entry
+            .failed_at
+            .is_some_and(|failed_at| now - failed_at >= RETRY_FAILED_SECONDS)
+        {
+            self.entries.remove(key);
+            return None;
+        }
+        entry.last_used = self.clock;
+        Some(entry.status.clone())
+    }
+
+    fn insert(&mut self, key: ImageKey, status: ImageStatus, bytes: usize) {
+        self.put(key, status, bytes, None);
+    }
+
+    fn insert_failed(&mut self, key: ImageKey, err: String, now: f64) {
+        self.put(key, ImageStatus::Failed(err), 0, Some(now));
+    }
+
+    fn put(&mut self, key: ImageKey, status: ImageStatus, bytes: usize, failed_at: Option<f64>) {
+        self.clock += 1;
+        let entry = Entry {
+            status,
+            bytes,
+            last_used: self.clock,
+            failed_at,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.bytes -= old.bytes;
+        }
+        self.bytes += bytes;
+        self.evict();
+    }
+
+    /// 超出预算时从最久没用的纹理开始清，刚放进来的那张保留
+    fn evict(&mut self) {
+        while self.bytes > self.budget {
+            let Some(key) = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.bytes > 0 && entry.last_used < self.clock)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.bytes;
+            }
+        }
+    }
+}
+
+impl Default for ImageEntries {
+    fn default() -> Self {
+        Self::new(TEXTURE_BUDGET_BYTES)
+    }
+}
+
+/// 图片纹理缓存，放在 egui 的 temp data 里，每个窗口的 context 各有一份。
+/// 解码交给 Bevy 的异步计算线程池，完成后唤醒 UI
+#[derive(Clone, Default)]
+struct ImageCache {
+    images: Arc<Mutex<ImageEntries>>,
+    decoded: Arc<Mutex<Vec<Decoded>>>,
+}
+
+impl ImageCache {
+    fn get(ctx: &Context) -> Self {
+        ctx.data_mut(|data| {
+            data.get_temp_mut_or_default::<ImageCache>(Id::new("image_cache"))
+                .clone()
+        })
+    }
+
+    fn status(&self, ctx: &Context, path: &str, full: bool) -> ImageStatus {
+        self.upload_decoded(ctx);
+        let key = (path.to_string(), full);
+        let now = ctx.input(|input| input.time);
+        let mut images = self.images.lock().unwrap();
+        if let Some(status) = images.get(&key, now) {
+            return status;
+        }
+        images.insert(key.clone(), ImageStatus::Loading, 0);
+
+        let decoded = self.decoded.clone();
+        let ctx = ctx.clone();
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let result = decode(&key.0, key.1);
+                decoded.lock().unwrap().push(Decoded { key, result });
+                ctx.request_repaint();
+            })
+            .detach();
+        ImageStatus::Loading
+    }
+
+    /// 纹理只能在 UI 线程创建，把后台解码好的图片上传
+    fn upload_decoded(&self, ctx: &Context) {
+        let decoded = std::mem::take(&mut *self.decoded.lock().unwrap());
+        if decoded.is_empty() {
+            return;
+        }
+        let now = ctx.input(|input| input.time);
+        let mut images = self.images.lock().unwrap();
+        for Decoded { key, result } in decoded {
+            match result {
+                Ok((image, size)) => {
+                    let name = format!("{}#{}", key.0, if key.1 { "full" } else { "thumb" });
+                    let bytes = image.pixels.len() * 4;
+                    let texture = ctx.load_texture(name, image, TextureOptions::LINEAR);
+                    images.insert(key, ImageStatus::Ready { texture, size }, bytes);
+                }
+                Err(err) => images.insert_failed(key, err, now),
+            }
+        }
+    }
+}
+
+fn decode(path: &str, full: bool) -> Result<(ColorImage, Vec2), String> {
+    // GIF 只显示第一帧
+    let image = image::open(path).map_err(|err| err.to_string())?;
+    let size = Vec2::new(image.width() as f32, image.height() as f32);
+    let image = if full {
+        if image.width() > FULL_IMAGE_MAX || image.height() > FULL_IMAGE_MAX {
+            image.resize(FULL_IMAGE_MAX, FULL_IMAGE_MAX, FilterType::Triangle)
+        } else {
+            image
+        }
+    } else {
+        image.thumbnail(THUMBNAIL_SIZE as u32, THUMBNAIL_SIZE as u32)
+    };
+    Ok((to_color_image(image), size))
+}
+
+fn to_color_image(image: DynamicImage) -> ColorImage {
+    let rgba = image.to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    ColorImage::from_rgba_unmultiplied(size, rgba.as_raw())
+}
+
+/// 消息里的缩略图，第一次调用时开始在后台解码
+pub fn thumbnail(ctx: &Context, path: &str) -> ImageStatus {
+    ImageCache::get(ctx).status(ctx, path, false)
+}
+
+/// 查看大图时用的原图
+pub fn full_image(ctx: &Context, path: &str) -> ImageStatus {
+    ImageCache::get(ctx).status(ctx, path, true)
+}
+
+/// 按比例缩小到 `max` 以内，不放大
+pub fn fit_size(size: Vec2, max: Vec2) -> Vec2 {
+    let scale = (max.x / size.x).min(max.y / size.y).min(1.0);
+    size * scale
+}
+
+pub fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+/// 查看大图，可以缩放、拖动，左右切换聊天里的其他图片
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lightbox {
+    pub message_id: String,
+    /// 一条消息里可能有多张图片
+    pub path: String,
+    pub zoom: f32,
+    pub pan: Vec2,
+}
+
+impl Lightbox {
+    pub fn new(message_id: &str, path: &str) -> Self {
+        Self {
+            message_id: message_id.to_string(),
+            path: path.to_string(),
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+        }
+    }
+}
+
+pub fn render_lightbox(ctx: &Context, ui_state: &mut ResMut<UiState>, theme: &NotificationTheme) {
+    let Some(mut lightbox) = ui_state.lightbox.clone() else {
+        return;
+    };
+    let images: Vec<(String, String)> = ui_state
+        .current_messages()
+        .iter()
+        .flat_map(|message| {
+            message
+                .content
+                .images()
+                .map(|path| (message.id.clone(), path.to_string()))
+        })
+        .collect();
+    let Some(index) = images
+        .iter()
+        .position(|(id, path)| *id == lightbox.message_id && *path == lightbox.path)
+    else {
+        ui_state.lightbox = None;
+        return;
+    };
+
+    let mut close = ctx.input(|input| input.key_pressed(Key::Escape));
+    let mut step: isize = 0;
+    ctx.input(|input| {
+        if input.key_pressed(Key::ArrowLeft) {
+            step = -1;
+        }
+        if input.key_pressed(Key::ArrowRight) {
+            step = 1;
+        }
+    });
+
+    let screen = ctx.screen_rect();
+    egui::Area::new(Id::new("image_lightbox"))
+        .fixed_pos(screen.min)
+        .order(egui::Order::Foreground)
+        .show(ctx, |ui| {
+            let (rect, response) = ui.allocate_exact_size(screen.size(), Sense::click_and_drag());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 0.0, Color32::from_black_alpha(220));
+
+            let path = &images[index].1;
+            let mut image_rect = Rect::NOTHING;
+            match full_image(ctx, path) {
+                ImageStatus::Ready { texture, size } => {
+                    let base = fit_size(size, rect.size() * 0.9);
+                    image_rect =
+                        Rect::from_center_size(rect.center() + lightbox.pan, base * lightbox.zoom);
+                    egui::Image::new(&texture).paint_at(ui, image_rect);
+                }
+                ImageStatus::Loading => {
+                    ui.put(
+                        Rect::from_center_size(rect.center(), Vec2::splat(32.0)),
+                        egui::Spinner::new().size(32.0),
+                    );
+                }
+                ImageStatus::Failed(err) => {
+                    painter.text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        format!("无法打开图片：{}", err),
+                        theme.fonts.content.clone(),
+                        Color32::WHITE,
+                    );
+                }
+            }
+
+            // 滚轮或触控板缩放，拖动平移，双击还原
+            if response.hovered() {
+                let (scroll, pinch) =
+                    ui.input(|input| (input.smooth_scroll_delta.y, input.zoom_delta()));
+                let factor = pinch * (1.0 + scroll * 0.002);
+                if factor != 1.0 {
+                    lightbox.zoom = (lightbox.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+                }
+            }
+            if response.dragged() {
+                lightbox.pan += response.drag_delta();
+            }
+            if response.double_clicked() {
+                lightbox.zoom = 1.0;
+                lightbox.pan = Vec2::ZERO;
+            } else if response.clicked()
+                && !response
+                    .interact_pointer_pos()
+                    .is_some_and(|pos| image_rect.contains(pos))
+            {
+                close = true;
+            }
+
+            let button = |text: &str| {
+                egui::Button::new(RichText::new(text).size(20.0).color(Color32::WHITE))
+                    .fill(Color32::from_black_alpha(120))
+                    .rounding(20.0)
+            };
+            let close_rect = Rect::from_center_size(
+                rect.right_top() + egui::vec2(-32.0, 32.0),
+                Vec2::splat(40.0),
+            );
+            if ui.put(close_rect, button("✕")).clicked() {
+                close = true;
+            }
+            if index > 0 {
+                let prev_rect = Rect::from_center_size(
+                    rect.left_center() + egui::vec2(40.0, 0.0),
+                    Vec2::splat(40.0),
+                );
+                if ui.put(prev_rect, button("◀")).clicked() {
+                    step = -1;
+                }
+            }
+            if index + 1 < images.len() {
+                let next_rect = Rect::from_center_size(
+                    rect.right_center() - egui::vec2(40.0, 0.0),
+                    Vec2::splat(40.0),
+                );
+                if ui.put(next_rect, button("▶")).clicked() {
+                    step = 1;
+                }
+            }
+            painter.text(
+                rect.center_bottom() - egui::vec2(0.0, 24.0),
+                egui::Align2::CENTER_CENTER,
+                format!(
+                    "{}  ·  {} / {}  ·  {:.0}%",
+                    file_name(path),
+                    index + 1,
+                    images.len(),
+                    lightbox.zoom * 100.0
+                ),
+                theme.fonts.label.clone(),
+                Color32::WHITE,
+            );
+        });
+
+    if close {
+        ui_state.lightbox = None;
+        return;
+    }
+    let target = index as isize + step;
+    if step != 0 && target >= 0 && (target as usize) < images.len() {
+        let (message_id, path) = &images[target as usize];
+        lightbox = Lightbox::new(message_id, path);
+    }
+    ui_state.lightbox = Some(lightbox);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ready(ctx: &Context, name: &str, side: usize) -> (ImageStatus, usize) {
+        let image = ColorImage::new([side, side], Color32::WHITE);
+        let bytes = image.pixels.len() * 4;
+        let texture = ctx.load_texture(name, image, TextureOptions::LINEAR);
+        let size = Vec2::splat(side as f32);
+        (ImageStatus::Ready { texture, size }, bytes)
+    }
+
+    fn key(path: &str) -> ImageKey {
+        (path.to_string(), false)
+    }
+
+    #[test]
+    fn evicts_least_recently_used_textures_over_budget() {
+        let ctx = Context::default();
+        // 每张 10x10 占 400 字节，预算放得下两张
+        let mut entries = ImageEntries::new(800);
+        for path in ["a", "b"] {
+            let (status, bytes) = ready(&ctx, path, 10);
+            entries.insert(key(path), status, bytes);
+        }
+        assert!(entries.get(&key("a"), 0.0).is_some());
+
+        let (status, bytes) = ready(&ctx, "c", 10);
+        entries.insert(key("c"), status, bytes);
+        assert!(entries.get(&key("b"), 0.0).is_none());
+        assert!(entries.get(&key("a"), 0.0).is_some());
+        assert!(entries.get(&key("c"), 0.0).is_some());
+        assert_eq!(entries.bytes, 800);
+    }
+
+    #[test]
+    fn keeps_the_newest_texture_even_if_it_is_over_budget() {
+        let ctx = Context::default();
+        let mut entries = ImageEntries::new(100);
+        entries.insert(key("loading"), ImageStatus::Loading, 0);
+        let (status, bytes) = ready(&ctx, "big", 10);
+        entries.insert(key("big"), status, bytes);
+        assert!(entries.get(&key("big"), 0.0).is_some());
+        // 没有纹理的条目不算在预算里，也不会被清掉
+        assert!(entries.get(&key("loading"), 0.0).is_some());
+    }
+
+    #[test]
+    fn retries_failed_decodes_after_a_pause() {
+        let mut entries = ImageEntries::new(100);
+        entries.insert_failed(key("missing"), "not found".to_string(), 10.0);
+        assert!(matches!(
+            entries.get(&key("missing"), 12.0),
+            Some(ImageStatus::Failed(_))
+        ));
+        assert!(entries.get(&key("missing"), 10.0 + RETRY_FAILED_SECONDS).is_none());
+        assert!(entries.entries.is_empty());
+    }
+}
@@ -0,0 +1,597 @@
+use std::{mem, sync::Arc};
+
+use bevy_egui::egui::{
+    self,
+    util::cache::{ComputerMut, FrameCache},
+    Color32, RichText, Ui,
+};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::{resources::NotificationTheme, search::highlight};
+
+/// 提及在 Markdown 里写成这个 scheme 的链接
+pub const MENTION_SCHEME: &str = "mention:";
+
+/// 消息里的链接只允许打开网页和邮件，本地文件和其他应用的 scheme 都不行
+fn is_openable_link(link: &str) -> bool {
+    url::Url::parse(link).is_ok_and(|url| matches!(url.scheme(), "http" | "https" | "mailto"))
+}
+
+/// 转义 Markdown 符号，文字原样显示
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 提及写成的链接，名字里的空格、括号和 Markdown 符号都要转义
+pub fn mention_link(user: &str) -> String {
+    let target: String = url::form_urlencoded::byte_serialize(user.as_bytes()).collect();
+    format!("[@{}]({}{})", escape_markdown(user), MENTION_SCHEME, target)
+}
+
+/// 链接段写成的 Markdown，没有文字时显示地址。
+/// 地址按 URL 规范重新编码后放在尖括号里，空格和括号都不会截断链接；解析不了的只显示文字
+pub fn link_markdown(text: Option<&str>, url: &str) -> String {
+    let label = escape_markdown(text.unwrap_or(url));
+    match url::Url::parse(url) {
+        Ok(url) => format!("[{}](<{}>)", label, url),
+        Err(_) => label,
+    }
+}
+
+/// 行内文本的一段，样式相同
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarkdownSpan {
+    pub text: String,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub link: Option<String>,
+}
+
+impl MarkdownSpan {
+    fn same_style(&self, other: &MarkdownSpan) -> bool {
+        self.strong == other.strong
+            && self.emphasis == other.emphasis
+            && self.strikethrough == other.strikethrough
+            && self.code == other.code
+            && self.link == other.link
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkdownBlockKind {
+    Paragraph(Vec<MarkdownSpan>),
+    Heading(u8, Vec<MarkdownSpan>),
+    /// 列表项，`marker` 为空时是同一项里的后续段落
+    Item {
+        depth: usize,
+        marker: String,
+        spans: Vec<MarkdownSpan>,
+    },
+    Code {
+        lang: Option<String>,
+        code: String,
+    },
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownBlock {
+    pub kind: MarkdownBlockKind,
+    /// 是否在引用里
+    pub quoted: bool,
+}
+
+/// 把消息内容解析成块。聊天里的单个换行保留为换行，不按 CommonMark 合并成空格
+pub fn parse_markdown(text: &str) -> Vec<MarkdownBlock> {
+    let mut builder = BlockBuilder::default();
+    let options = Options::ENABLE_STRIKETHROUGH;
+    for event in Parser::new_ext(text, options) {
+        builder.push(event);
+    }
+    builder.flush();
+    builder.blocks
+}
+
+#[derive(Default)]
+struct BlockBuilder {
+    blocks: Vec<MarkdownBlock>,
+    spans: Vec<MarkdownSpan>,
+    style: MarkdownSpan,
+    heading: Option<u8>,
+    quote_depth: usize,
+    /// 每层列表的下一个序号，无序列表为空
+    lists: Vec<Option<u64>>,
+    marker: Option<String>,
+    code: Option<(Option<String>, String)>,
+}
+
+impl BlockBuilder {
+    fn push(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some((_, code)) => code.push_str(&text),
+                None => self.text(&text),
+            },
+            Event::Code(code) => self.spans.push(MarkdownSpan {
+                text: code.to_string(),
+                code: true,
+                ..self.style.clone()
+            }),
+            Event::Html(html) | Event::InlineHtml(html) => self.text(&html),
+            Event::SoftBreak | Event::HardBreak => self.text("\n"),
+            Event::Rule => {
+                self.flush();
+                self.push_block(MarkdownBlockKind::Rule);
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush();
+                self.heading = Some(heading_level(level));
+            }
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) if !lang.is_empty() => Some(lang.to_string()),
+                    _ => None,
+                };
+                self.code = Some((lang, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                self.marker = Some(match self.lists.last_mut() {
+                    Some(Some(next)) => {
+                        *next += 1;
+                        format!("{}.", *next - 1)
+                    }
+                    _ => "•".to_string(),
+                });
+            }
+            Tag::Emphasis => self.style.emphasis = true,
+            Tag::Strong => self.style.strong = true,
+            Tag::Strikethrough => self.style.strikethrough = true,
+            Tag::Link { dest_url, .. } => self.style.link = Some(dest_url.to_string()),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Item => self.flush(),
+            TagEnd::Heading(_) => {
+                self.flush();
+                self.heading = None;
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => {
+                if let Some((lang, code)) = self.code.take() {
+                    let code = code.trim_end_matches('\n').to_string();
+                    self.push_block(MarkdownBlockKind::Code { lang, code });
+                }
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+            }
+            TagEnd::Emphasis => self.style.emphasis = false,
+            TagEnd::Strong => self.style.strong = false,
+            TagEnd::Strikethrough => self.style.strikethrough = false,
+            TagEnd::Link => self.style.link = None,
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        // 和上一段样式相同时直接拼上，减少控件数量
+        if let Some(last) = self.spans.last_mut() {
+            if last.text != "\n" && text != "\n" && last.same_style(&self.style) {
+                last.text.push_str(text);
+                return;
+            }
+        }
+        self.spans.push(MarkdownSpan {
+            text: text.to_string(),
+            ..self.style.clone()
+        });
+    }
+
+    fn flush(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+        let spans = mem::take(&mut self.spans);
+        let kind = if let Some(level) = self.heading {
+            MarkdownBlockKind::Heading(level, spans)
+        } else if !self.lists.is_empty() {
+            MarkdownBlockKind::Item {
+                depth: self.lists.len() - 1,
+                marker: self.marker.take().unwrap_or_default(),
+                spans,
+            }
+        } else {
+            MarkdownBlockKind::Paragraph(spans)
+        };
+        self.push_block(kind);
+    }
+
+    fn push_block(&mut self, kind: MarkdownBlockKind) {
+        self.blocks.push(MarkdownBlock {
+            kind,
+            quoted: self.quote_depth > 0,
+        });
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+#[derive(Default)]
+struct MarkdownParser;
+
+impl ComputerMut<&str, Arc<Vec<MarkdownBlock>>> for MarkdownParser {
+    fn compute(&mut self, text: &str) -> Arc<Vec<MarkdownBlock>> {
+        Arc::new(parse_markdown(text))
+    }
+}
+
+/// 解析结果按内容缓存，没有显示的消息下一帧会被清掉
+type MarkdownCache = FrameCache<Arc<Vec<MarkdownBlock>>, MarkdownParser>;
+
+/// `terms` 不为空时高亮其中的词，聊天内搜索用
+pub fn render_markdown(
+    ui: &mut Ui,
+    text: &str,
+    text_color: Color32,
+    theme: &NotificationTheme,
+    terms: &[String],
+) {
+    let blocks = ui
+        .ctx()
+        .memory_mut(|mem| mem.caches.cache::<MarkdownCache>().get(text));
+    let colors = theme.current_colors();
+    ui.vertical(|ui| {
+        for block in blocks.iter() {
+            if !block.quoted {
+                render_block(ui, &block.kind, text_color, theme, terms);
+                continue;
+            }
+            // 引用左侧画一条竖线
+            let response = egui::Frame::none()
+                .inner_margin(egui::Margin {
+                    left: 10.0,
+                    ..Default::default()
+                })
+                .show(ui, |ui| {
+                    render_block(ui, &block.kind, theme.text_styles.chat_time.color, theme, terms)
+                })
+                .response;
+            let rect = response.rect;
+            ui.painter().line_segment(
+                [rect.left_top(), rect.left_bottom()],
+                egui::Stroke::new(3.0, colors.divider),
+            );
+        }
+    });
+}
+
+fn render_block(
+    ui: &mut Ui,
+    kind: &MarkdownBlockKind,
+    text_color: Color32,
+    theme: &NotificationTheme,
+    terms: &[String],
+) {
+    match kind {
+        MarkdownBlockKind::Paragraph(spans) => {
+            render_spans(ui, spans, None, text_color, theme, terms)
+        }
+        MarkdownBlockKind::Heading(level, spans) => {
+            let size = match level {
+                1 => 22.0,
+                2 => 19.0,
+                _ => 16.0,
+            };
+            render_spans(ui, spans, Some(size), text_color, theme, terms);
+        }
+        MarkdownBlockKind::Item {
+            depth,
+            marker,
+            spans,
+        } => {
+            ui.horizontal_top(|ui| {
+                ui.add_space(*depth as f32 * 16.0);
+                // 续段的 marker 为空，占同样的宽度和上一段对齐
+                ui.add_sized(
+                    egui::vec2(16.0, 0.0),
+                    egui::Label::new(RichText::new(marker).color(text_color)),
+                );
+                render_spans(ui, spans, None, text_color, theme, terms);
+            });
+        }
+        MarkdownBlockKind::Code { code, .. } => {
+            egui::Frame::none()
+                .fill(theme.current_colors().hover)
+                .rounding(4.0)
+                .inner_margin(6.0)
+                .show(ui, |ui| {
+                    ui.label(RichText::new(code).monospace().color(text_color));
+                });
+        }
+        MarkdownBlockKind::Rule => {
+            ui.separator();
+        }
+    }
+}
+
+fn render_spans(
+    ui: &mut Ui,
+    spans: &[MarkdownSpan],
+    size: Option<f32>,
+    text_color: Color32,
+    theme: &NotificationTheme,
+    terms: &[String],
+) {
+    let accent = theme.current_colors().accent;
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for span in spans {
+            if span.text == "\n" {
+                ui.end_row();
+                continue;
+            }
+            let text = span_text(span, &span.text, size, text_color);
+            match &span.link {
+                Some(url) if url.starts_with(MENTION_SCHEME) => {
+                    ui.label(text.color(theme.current_colors().accent).strong());
+                }
+                Some(url) if is_openable_link(url) => {
+                    ui.hyperlink_to(text.color(theme.current_colors().accent), url)
+                        .on_hover_text(url);
+                }
+                // 其他链接来自对方的消息，不能点开，按普通文字显示
+                _ if terms.is_empty() => {
+                    ui.label(text);
+                }
+                _ => {
+                    // 命中的部分用强调色
+                    let matches = highlight(&span.text, terms).highlights;
+                    let mut cursor = 0;
+                    for range in matches {
+                        if cursor < range.start {
+                            let before = &span.text[cursor..range.start];
+                            ui.label(span_text(span, before, size, text_color));
+                        }
+                        let hit = &span.text[range.clone()];
+                        ui.label(span_text(span, hit, size, accent).strong());
+                        cursor = range.end;
+                    }
+                    if cursor < span.text.len() {
+                        ui.label(span_text(span, &span.text[cursor..], size, text_color));
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// 按 `span` 的样式显示 `text`，`text` 可以是 `span` 的一部分
+fn span_text(span: &MarkdownSpan, text: &str, size: Option<f32>, color: Color32) -> RichText {
+    let mut text = RichText::new(text).color(color);
+    if let Some(size) = size {
+        text = text.size(size).strong();
+    }
+    if span.strong {
+        text = text.strong();
+    }
+    if span.emphasis {
+        text = text.italics();
+    }
+    if span.strikethrough {
+        text = text.strikethrough();
+    }
+    if span.code {
+        text = text.code();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str) -> MarkdownSpan {
+        MarkdownSpan {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(text: &str) -> Vec<MarkdownBlockKind> {
+        parse_markdown(text).into_iter().map(|block| block.kind).collect()
+    }
+
+    #[test]
+    fn keeps_single_newlines_and_merges_same_style_text() {
+        assert_eq!(
+            kinds("hello *world*\nbye"),
+            [MarkdownBlockKind::Paragraph(vec![
+                span("hello "),
+                MarkdownSpan {
+                    emphasis: true,
+                    ..span("world")
+                },
+                span("\n"),
+                span("bye"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn numbers_ordered_lists_and_nests_bullets() {
+        assert_eq!(
+            kinds("3. first\n4. second\n   - inner"),
+            [
+                MarkdownBlockKind::Item {
+                    depth: 0,
+                    marker: "3.".to_string(),
+                    spans: vec![span("first")],
+                },
+                MarkdownBlockKind::Item {
+                    depth: 0,
+                    marker: "4.".to_string(),
+                    spans: vec![span("second")],
+                },
+                MarkdownBlockKind::Item {
+                    depth: 1,
+                    marker: "•".to_string(),
+                    spans: vec![span("inner")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fenced_code_keeps_language_and_text() {
+        assert_eq!(
+            kinds("```rust\nfn main() {}\n\n```\nafter"),
+            [
+                MarkdownBlockKind::Code {
+                    lang: Some("rust".to_string()),
+                    code: "fn main() {}".to_string(),
+                },
+                MarkdownBlockKind::Paragraph(vec![span("after")]),
+            ]
+        );
+        assert_eq!(
+            kinds("```\n**not bold**\n```"),
+            [MarkdownBlockKind::Code {
+                lang: None,
+                code: "**not bold**".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn links_and_mentions_carry_their_target() {
+        let link = |text: &str, url: &str| MarkdownSpan {
+            link: Some(url.to_string()),
+            ..span(text)
+        };
+        assert_eq!(
+            kinds("see [docs](https://example.com) and [@Ray](mention:Ray)"),
+            [MarkdownBlockKind::Paragraph(vec![
+                span("see "),
+                link("docs", "https://example.com"),
+                span(" and "),
+                link("@Ray", "mention:Ray"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn only_web_and_mail_links_can_be_opened() {
+        assert!(is_openable_link("https://example.com/a?b=c"));
+        assert!(is_openable_link("HTTP://example.com"));
+        assert!(is_openable_link("mailto:ray@example.com"));
+        assert!(!is_openable_link("file:///etc/passwd"));
+        assert!(!is_openable_link("javascript:alert(1)"));
+        assert!(!is_openable_link("slack://open"));
+        assert!(!is_openable_link("../secret.txt"));
+        assert!(!is_openable_link("mention:Ray"));
+    }
+
+    #[test]
+    fn link_text_and_targets_are_escaped() {
+        let link = |text: &str, url: &str| MarkdownSpan {
+            link: Some(url.to_string()),
+            ..span(text)
+        };
+        assert_eq!(
+            kinds(&link_markdown(Some("a ] *b*"), "https://example.com/a b)c")),
+            [MarkdownBlockKind::Paragraph(vec![link(
+                "a ] *b*",
+                "https://example.com/a%20b)c"
+            )])]
+        );
+        assert_eq!(
+            kinds(&link_markdown(None, "https://example.com/x_y_")),
+            [MarkdownBlockKind::Paragraph(vec![link(
+                "https://example.com/x_y_",
+                "https://example.com/x_y_"
+            )])]
+        );
+        assert_eq!(
+            kinds(&link_markdown(Some("[x](y)"), "not a url")),
+            [MarkdownBlockKind::Paragraph(vec![span("[x](y)")])]
+        );
+    }
+
+    #[test]
+    fn mention_links_survive_spaces_and_brackets() {
+        let blocks = kinds(&format!("hi {}!", mention_link("Ray Chen (QA)] *x*")));
+        let [MarkdownBlockKind::Paragraph(spans)] = blocks.as_slice() else {
+            panic!("expected one paragraph, got {:?}", blocks);
+        };
+        let mention: Vec<_> = spans.iter().filter(|span| span.link.is_some()).collect();
+        let text: String = mention.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(text, "@Ray Chen (QA)] *x*");
+        assert!(mention[0].link.as_ref().unwrap().starts_with(MENTION_SCHEME));
+        assert_eq!(spans.last().unwrap().text, "!");
+    }
+
+    #[test]
+    fn block_quotes_mark_their_blocks() {
+        let blocks = parse_markdown("> quoted\n> - item\n\nplain");
+        let quoted: Vec<bool> = blocks.iter().map(|block| block.quoted).collect();
+        assert_eq!(quoted, [true, true, false]);
+        assert_eq!(
+            blocks[2].kind,
+            MarkdownBlockKind::Paragraph(vec![span("plain")])
+        );
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        assert_eq!(
+            kinds("## Title\n\n---"),
+            [
+                MarkdownBlockKind::Heading(2, vec![span("Title")]),
+                MarkdownBlockKind::Rule,
+            ]
+        );
+    }
+}
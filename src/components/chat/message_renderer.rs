@@ -1,11 +1,27 @@
+use std::path::Path;
+
+use bevy::log::warn;
+use url::Url;
 use bevy_egui::{
     self,
-    egui::{Button, CollapsingHeader, RichText, Ui},
+    egui::{self, Button, RichText, Ui},
 };
 
-use crate::resources::NotificationTheme;
+use crate::resources::{NotificationTheme, ThemeMode};
+
+use super::{
+    code_highlight::{code_colors, highlight_code, language_label},
+    image_viewer::{file_name, fit_size, thumbnail, ImageStatus, THUMBNAIL_SIZE},
+    markdown::{link_markdown, mention_link, render_markdown},
+    request_file_dialog, Attachment, FileDialogRequest, ChatMainStyle, ChatMessage, MessageRenderer, Segment,
+};
 
-use super::{ChatMainStyle, ChatMessage, MessageRenderer};
+/// 超过这么多行的代码默认折叠
+const CODE_COLLAPSE_LINES: usize = 20;
+/// 折叠时显示的行数
+const CODE_PREVIEW_LINES: usize = 10;
+/// 点击复制后“已复制”的显示时间
+const COPIED_SECONDS: f64 = 2.0;
 
 pub struct TextMessageRenderer;
 pub struct FileMessageRenderer;
@@ -16,11 +32,24 @@ impl MessageRenderer for TextMessageRenderer {
     fn render(
         &self,
         ui: &mut Ui,
-        message: &ChatMessage,
+        _message: &ChatMessage,
+        segments: &[Segment],
         style: &ChatMainStyle,
-        _theme: &NotificationTheme,
+        theme: &NotificationTheme,
     ) {
-        ui.label(RichText::new(&message.content).color(style.colors.text));
+        render_markdown(ui, &inline_markdown(segments), style.colors.text, theme, &[]);
+    }
+
+    fn render_highlighted(
+        &self,
+        ui: &mut Ui,
+        _message: &ChatMessage,
+        segments: &[Segment],
+        style: &ChatMainStyle,
+        theme: &NotificationTheme,
+        terms: &[String],
+    ) {
+        render_markdown(ui, &inline_markdown(segments), style.colors.text, theme, terms);
     }
 }
 
@@ -29,14 +58,98 @@ impl MessageRenderer for CodeMessageRenderer {
         &self,
         ui: &mut Ui,
         message: &ChatMessage,
+        segments: &[Segment],
         _style: &ChatMainStyle,
-        _theme: &NotificationTheme,
+        theme: &NotificationTheme,
     ) {
-        CollapsingHeader::new("code")
-            .id_salt(&message.id)
-            .default_open(true)
+        let Some(Segment::Code { language, code }) = segments.first() else {
+            return;
+        };
+        let colors = theme.current_colors();
+        let language = language.as_deref().unwrap_or("txt");
+        let line_count = code.lines().count().max(1);
+        // 一条消息里可能有多段代码
+        let expanded_id = egui::Id::new(("code_expanded", &message.id, code));
+        let copied_id = egui::Id::new(("code_copied", &message.id, code));
+        let long = line_count > CODE_COLLAPSE_LINES;
+        let expanded = !long || ui.data(|data| data.get_temp(expanded_id).unwrap_or(false));
+        let dark = theme.mode == ThemeMode::Dark;
+        let (background, foreground) = code_colors(dark);
+
+        egui::Frame::none()
+            .fill(background)
+            .stroke(egui::Stroke::new(1.0, colors.border))
+            .rounding(6.0)
+            .inner_margin(8.0)
             .show(ui, |ui| {
-                ui.code(&message.content);
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(language_label(language))
+                            .strong()
+                            .color(foreground),
+                    );
+                    ui.label(
+                        RichText::new(format!("{} 行", line_count))
+                            .color(theme.text_styles.chat_time.color),
+                    );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        let now = ui.input(|input| input.time);
+                        let copied_at: Option<f64> = ui.data(|data| data.get_temp(copied_id));
+                        let copied = copied_at.is_some_and(|at| now - at < COPIED_SECONDS);
+                        let label = if copied { "已复制" } else { "复制" };
+                        if ui.small_button(label).clicked() {
+                            ui.ctx().copy_text(code.clone());
+                            ui.data_mut(|data| data.insert_temp(copied_id, now));
+                            ui.ctx().request_repaint_after_secs(COPIED_SECONDS as f32);
+                        }
+                    });
+                });
+                ui.separator();
+
+                let code = if expanded {
+                    code.as_str()
+                } else {
+                    let end = code
+                        .match_indices('\n')
+                        .nth(CODE_PREVIEW_LINES - 1)
+                        .map_or(code.len(), |(index, _)| index);
+                    &code[..end]
+                };
+                let shown_lines = if expanded {
+                    line_count
+                } else {
+                    CODE_PREVIEW_LINES
+                };
+                let font = egui::TextStyle::Monospace.resolve(ui.style());
+                let job = highlight_code(ui.ctx(), code, language, dark, &font);
+                // 长行不折行，横向滚动
+                egui::ScrollArea::horizontal()
+                    .id_salt(&message.id)
+                    .show(ui, |ui| {
+                        ui.horizontal_top(|ui| {
+                            let numbers = (1..=shown_lines)
+                                .map(|line| line.to_string())
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            ui.label(
+                                RichText::new(numbers)
+                                    .font(font.clone())
+                                    .color(theme.text_styles.chat_time.color),
+                            );
+                            ui.add(egui::Label::new(job).extend());
+                        });
+                    });
+
+                if long {
+                    let text = if expanded {
+                        "收起".to_string()
+                    } else {
+                        format!("展开全部 {} 行", line_count)
+                    };
+                    if ui.link(text).clicked() {
+                        ui.data_mut(|data| data.insert_temp(expanded_id, !expanded));
+                    }
+                }
             });
     }
 }
@@ -45,11 +158,57 @@ impl MessageRenderer for FileMessageRenderer {
     fn render(
         &self,
         ui: &mut Ui,
-        message: &ChatMessage,
-        _style: &ChatMainStyle,
-        _theme: &NotificationTheme,
+        _message: &ChatMessage,
+        segments: &[Segment],
+        style: &ChatMainStyle,
+        theme: &NotificationTheme,
     ) {
-        ui.add(Button::new(format!("📎 {}", &message.content)).frame(false));
+        let Some(Segment::File { attachment }) = segments.first() else {
+            return;
+        };
+        let exists = attachment.exists();
+        egui::Frame::none()
+            .stroke(egui::Stroke::new(1.0, theme.current_colors().border))
+            .rounding(6.0)
+            .inner_margin(8.0)
+            .show(ui, |ui| {
+                ui.set_min_width(220.0);
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::Image::new(attachment.kind().icon())
+                            .fit_to_exact_size(egui::vec2(36.0, 36.0)),
+                    );
+                    ui.vertical(|ui| {
+                        ui.label(
+                            RichText::new(&attachment.name)
+                                .strong()
+                                .color(style.colors.text),
+                        )
+                        .on_hover_text(&attachment.path);
+                        let detail = if exists {
+                            attachment.size_label()
+                        } else {
+                            format!("{} · 文件已移动或删除", attachment.size_label())
+                        };
+                        ui.label(
+                            RichText::new(detail)
+                                .size(12.0)
+                                .color(theme.text_styles.chat_time.color),
+                        );
+                    });
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(exists, Button::new("打开")).clicked() {
+                        open_file(ui.ctx(), attachment);
+                    }
+                    if ui.add_enabled(exists, Button::new("另存为")).clicked() {
+                        request_file_dialog(
+                            ui.ctx(),
+                            FileDialogRequest::SaveAs(attachment.clone()),
+                        );
+                    }
+                });
+            });
     }
 }
 
@@ -58,9 +217,59 @@ impl MessageRenderer for ImageMessageRenderer {
         &self,
         ui: &mut Ui,
         _message: &ChatMessage,
+        segments: &[Segment],
         _style: &ChatMainStyle,
-        _theme: &NotificationTheme,
+        theme: &NotificationTheme,
     ) {
-        ui.label("ToDo images");
+        // 点击图片打开大图，见 `ChatMainView::render_message`
+        let Some(Segment::Image { path }) = segments.first() else {
+            return;
+        };
+        match thumbnail(ui.ctx(), path) {
+            ImageStatus::Ready { texture, .. } => {
+                let size = fit_size(texture.size_vec2(), egui::Vec2::splat(THUMBNAIL_SIZE));
+                ui.add(
+                    egui::Image::new(&texture)
+                        .fit_to_exact_size(size)
+                        .rounding(6.0),
+                );
+            }
+            ImageStatus::Loading => {
+                let (rect, _) =
+                    ui.allocate_exact_size(egui::vec2(160.0, 120.0), egui::Sense::hover());
+                ui.put(rect, egui::Spinner::new());
+            }
+            ImageStatus::Failed(err) => {
+                ui.label(
+                    RichText::new(format!("🖼 {}", file_name(path)))
+                        .color(theme.text_styles.chat_time.color),
+                )
+                .on_hover_text(format!("无法加载图片：{}", err));
+            }
+        }
+    }
+}
+
+/// 行内段拼成 Markdown，提及写成 `mention:` 开头的链接，渲染时单独处理
+fn inline_markdown(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Text { text } => text.clone(),
+            Segment::Mention { user } => mention_link(user),
+            Segment::Link { url, text } => link_markdown(text.as_deref(), url),
+            Segment::Emoji { emoji } => emoji.clone(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// 用系统默认的程序打开附件
+fn open_file(ctx: &egui::Context, attachment: &Attachment) {
+    let path = Path::new(&attachment.path);
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match Url::from_file_path(&path) {
+        Ok(url) => ctx.open_url(egui::OpenUrl::same_tab(url)),
+        Err(()) => warn!("cannot open {}: not an absolute path", path.display()),
     }
 }
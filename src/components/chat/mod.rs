@@ -1,19 +1,31 @@
 mod model;
+mod attachment;
 mod chat_model;
 mod chat_style;
 mod constants;
 mod controller;
 mod event;
+mod image_viewer;
 mod chat_message;
 mod chat_view;
+mod content;
+mod code_highlight;
+mod markdown;
 mod message_renderer;
+mod time_format;
+mod timeline;
 mod view;
 
 pub use model::*;
+pub use attachment::*;
 pub use chat_model::*;
 pub use constants::AVATAR_COLORS;
 pub use event::*;
+pub use image_viewer::{render_lightbox, Lightbox};
 pub use chat_view::*;
+pub use content::*;
 pub use message_renderer::*;
+pub use time_format::*;
+pub use timeline::*;
 pub use controller::*;
 pub use view::*;
\ No newline at end of file
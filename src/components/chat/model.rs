@@ -1,9 +1,58 @@
-use std::collections::HashMap;
+use chrono::{DateTime, Local, Utc};
+use ulid::Ulid;
+
+use crate::resources::ChatData;
+
+use super::{format_list_time, MessageContent};
+
+/// 当前登录用户在消息里的发送者名字
+pub const CURRENT_USER: &str = "You";
 
 #[derive(Debug, Default)]
 pub struct ChatListModel {
     pub items: Vec<ChatListItem>,
     pub filter: ChatFilter,
+    /// 所有聊天用到的标签，右键菜单里选择
+    pub tags: Vec<String>,
+}
+
+/// 聊天的通知级别，不是 `All` 的都算免打扰
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotificationLevel {
+    #[default]
+    All,
+    /// 只有 @ 自己的消息才提醒
+    Mentions,
+    None,
+}
+
+impl NotificationLevel {
+    pub const ALL: [NotificationLevel; 3] = [
+        NotificationLevel::All,
+        NotificationLevel::Mentions,
+        NotificationLevel::None,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotificationLevel::All => "所有消息",
+            NotificationLevel::Mentions => "仅 @我 的消息",
+            NotificationLevel::None => "不通知",
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        *self != NotificationLevel::All
+    }
+
+    /// 这个聊天的未读是否需要提醒，提醒的显示红色数字，否则只显示灰点
+    pub fn notifies(&self, mentions_me: bool) -> bool {
+        match self {
+            NotificationLevel::All => true,
+            NotificationLevel::Mentions => mentions_me,
+            NotificationLevel::None => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -11,17 +60,93 @@ pub struct ChatListItem {
     pub id: String,
     pub name: String,
     pub avatar: String,
+    /// 最新一条消息的预览，带发送者前缀
     pub last_message: Option<String>,
+    pub last_time: Option<String>,
+    pub last_activity: Option<DateTime<Utc>>,
     pub is_selected: bool,
     pub unread_count: Option<i32>,
     pub is_pinned: bool,
+    /// 最后一条消息是自己发的时候，它的已读状态
+    pub read_label: Option<String>,
+    pub chat_type: ChatType,
+    /// 单聊对象的在线状态，其他类型为空
+    pub presence: Option<Presence>,
+    pub is_flagged: bool,
+    pub is_done: bool,
+    pub tags: Vec<String>,
+    /// 有未读的消息 @ 了自己
+    pub mentions_me: bool,
+    pub is_cloud_doc: bool,
+    pub notification: NotificationLevel,
+    pub is_nav_pinned: bool,
+    pub is_marked_unread: bool,
+    pub is_detached: bool,
 }
 
+/// 聊天列表的分组，除了“已完成”，其他分组都不显示已完成的聊天
 #[derive(Clone, Debug, Copy, PartialEq, Default)]
 pub enum ChatFilter {
     #[default]
     All,
     Pinned,
+    Flagged,
+    Mentions,
+    Tagged,
+    Direct,
+    Group,
+    Docs,
+    Topic,
+    Done,
+}
+
+impl ChatFilter {
+    /// 侧边栏里按顺序显示的分组
+    pub const GROUPS: [ChatFilter; 8] = [
+        ChatFilter::Flagged,
+        ChatFilter::Mentions,
+        ChatFilter::Tagged,
+        ChatFilter::Direct,
+        ChatFilter::Group,
+        ChatFilter::Docs,
+        ChatFilter::Topic,
+        ChatFilter::Done,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChatFilter::All => "全部",
+            ChatFilter::Pinned => "置顶",
+            ChatFilter::Flagged => "标记",
+            ChatFilter::Mentions => "@我",
+            ChatFilter::Tagged => "标签",
+            ChatFilter::Direct => "单聊",
+            ChatFilter::Group => "群组",
+            ChatFilter::Docs => "云文档",
+            ChatFilter::Topic => "话题",
+            ChatFilter::Done => "已完成",
+        }
+    }
+
+    pub fn matches(&self, item: &ChatListItem) -> bool {
+        if *self == ChatFilter::Done {
+            return item.is_done;
+        }
+        !item.is_done
+            && match self {
+                ChatFilter::All | ChatFilter::Done => true,
+                ChatFilter::Pinned => item.is_pinned,
+                ChatFilter::Flagged => item.is_flagged,
+                ChatFilter::Mentions => item.mentions_me,
+                ChatFilter::Tagged => !item.tags.is_empty(),
+                ChatFilter::Direct => item.chat_type == ChatType::Direct,
+                ChatFilter::Group => {
+                    matches!(item.chat_type, ChatType::Group | ChatType::TopicGroup)
+                }
+                ChatFilter::Docs => item.is_cloud_doc,
+                ChatFilter::Topic => item.chat_type == ChatType::TopicGroup,
+            }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -33,23 +158,158 @@ pub struct Chat {
     pub last_message: Option<String>,
     pub chat_type: ChatType,
     pub pin: bool,
+    pub flagged: bool,
+    pub done: bool,
+    pub tags: Vec<String>,
+    /// 云文档的评论和通知会话，归到“云文档”分组
+    pub cloud_doc: bool,
+    pub notification: NotificationLevel,
+    /// 固定在左侧导航栏
+    pub nav_pinned: bool,
+    /// 手动标为未读，打开聊天后清除
+    pub marked_unread: bool,
+    /// 在独立窗口里打开
+    pub detached: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum ChatType {
     #[default]
     Group,
+    /// 单聊，聊天名就是对方的名字
+    Direct,
+    Bot,
+    /// 话题群，每条新消息是一个话题，其他消息是对话题的回复
+    TopicGroup,
+    /// 公告频道，只能看不能发
+    Announcement,
+}
+
+impl ChatType {
+    /// 有多个成员，需要显示成员数和消息发送者
+    pub fn is_multi_member(&self) -> bool {
+        matches!(
+            self,
+            ChatType::Group | ChatType::TopicGroup | ChatType::Announcement
+        )
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        *self == ChatType::Announcement
+    }
+
+    /// 聊天名旁边的标签
+    pub fn badge(&self) -> Option<&'static str> {
+        match self {
+            ChatType::Group | ChatType::Direct => None,
+            ChatType::Bot => Some("机器人"),
+            ChatType::TopicGroup => Some("话题"),
+            ChatType::Announcement => Some("频道"),
+        }
+    }
+}
+
+/// 单聊对象的在线状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Presence {
+    Online,
+    Away,
+    #[default]
+    Offline,
+}
+
+impl Presence {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Presence::Online => "在线",
+            Presence::Away => "离开",
+            Presence::Offline => "离线",
+        }
+    }
+}
+
+/// 自己的状态，显示在头像旁边。`do_not_disturb` 时不弹出提醒
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserStatus {
+    pub icon: String,
+    pub text: String,
+    /// 到这个时间自动清除，为空时一直保留
+    pub expires_at: Option<DateTime<Utc>>,
+    pub do_not_disturb: bool,
+}
+
+impl UserStatus {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// 还要多久到期，不会过期时为空，已经到期时为零
+    pub fn time_left(&self, now: DateTime<Utc>) -> Option<std::time::Duration> {
+        self.expires_at
+            .map(|expires_at| (expires_at - now).to_std().unwrap_or_default())
+    }
+
+    /// 例如 `Until 17:00`，跨天时带上日期
+    pub fn expiry_label(&self, now: &DateTime<Local>) -> String {
+        match self.expires_at {
+            None => "Until cleared".to_string(),
+            Some(expires_at) => {
+                let local = expires_at.with_timezone(&Local);
+                if local.date_naive() == now.date_naive() {
+                    format!("Until {}", local.format("%H:%M"))
+                } else {
+                    format!("Until {}", local.format("%m-%d %H:%M"))
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct ChatMessage {
     pub id: String,
+    /// 客户端生成的幂等键，重发时保持不变，用来把本地回显和服务端返回的消息对应起来
+    pub client_key: String,
     pub chat_id: String,
     pub sender: String,
     pub avatar: String,
-    pub content: String,
-    pub timestamp: String,
+    pub content: MessageContent,
+    pub timestamp: DateTime<Utc>,
+    /// 由内容决定，见 `MessageContent::message_type`
     pub message_type: MessageType,
+    pub delivery: DeliveryState,
+    /// 话题群里回复的话题，值是话题第一条消息的 id
+    pub reply_to: Option<String>,
+}
+
+/// 一条消息的已读情况，由聊天成员的已读位置计算得出
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadReceipt {
+    pub read_by: Vec<String>,
+    pub unread_count: i32,
+}
+
+impl ReadReceipt {
+    pub fn is_all_read(&self) -> bool {
+        self.unread_count == 0
+    }
+
+    pub fn label(&self) -> String {
+        if self.is_all_read() {
+            "已读".to_string()
+        } else {
+            format!("未读 {} 人", self.unread_count)
+        }
+    }
+}
+
+/// 自己发出的消息的投递状态，收到的消息始终是 `Sent`
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DeliveryState {
+    #[default]
+    Sent,
+    Pending,
+    Failed,
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
@@ -61,35 +321,201 @@ pub enum MessageType {
     Code,
 }
 
+impl ChatMessage {
+    /// 生成按时间排序、不会重复的 id（ULID）
+    pub fn generate_id() -> String {
+        Ulid::new().to_string()
+    }
+
+    pub fn is_own(&self) -> bool {
+        self.sender == CURRENT_USER
+    }
+
+    /// 是否 @ 了自己或所有人
+    pub fn mentions_me(&self) -> bool {
+        self.content.mentions_me()
+    }
+
+    /// 列表和通知里显示的一行摘要，非文本内容用占位符
+    pub fn preview(&self) -> String {
+        self.content.preview()
+    }
+
+    /// 判断两条消息是否是同一次发送
+    pub fn is_same_send(&self, other: &ChatMessage) -> bool {
+        self.id == other.id || (!self.client_key.is_empty() && self.client_key == other.client_key)
+    }
+}
+
 impl ChatListModel {
-    pub fn new(chats: &[Chat], selected_id: &str, unread_count: &HashMap<String, i32>) -> Self {
-        let items = chats
+    pub fn new(chat_data: &ChatData, selected_id: &str, filter: ChatFilter) -> Self {
+        let now = Local::now();
+        let mut items: Vec<ChatListItem> = chat_data
+            .chats
             .iter()
-            .map(|chat| ChatListItem {
-                id: chat.id.clone(),
-                name: chat.name.clone(),
-                avatar: chat.avatar.clone(),
-                last_message: chat.last_message.clone(),
-                is_selected: chat.id == selected_id,
-                unread_count: unread_count.get(&chat.id).copied(),
-                is_pinned: chat.pin,
-                ..Default::default()
+            .map(|chat| {
+                let latest = chat_data.latest_message(&chat.id);
+                ChatListItem {
+                    id: chat.id.clone(),
+                    name: chat.name.clone(),
+                    avatar: chat.avatar.clone(),
+                    // 还没有消息时显示聊天自带的介绍。单聊和机器人只在自己发的消息前加前缀
+                    last_message: latest
+                        .map(|message| {
+                            if message.is_own() {
+                                format!("我: {}", message.preview())
+                            } else if chat.chat_type.is_multi_member() {
+                                format!("{}: {}", message.sender, message.preview())
+                            } else {
+                                message.preview()
+                            }
+                        })
+                        .or_else(|| chat.last_message.clone()),
+                    last_time: latest.map(|message| format_list_time(&message.timestamp, &now)),
+                    last_activity: latest.map(|message| message.timestamp),
+                    is_selected: chat.id == selected_id,
+                    unread_count: chat_data.unread_counts.get(&chat.id).copied(),
+                    is_pinned: chat.pin,
+                    read_label: latest
+                        .filter(|message| message.is_own())
+                        .map(|message| chat_data.read_receipt(&chat.id, message).label()),
+                    chat_type: chat.chat_type.clone(),
+                    presence: (chat.chat_type == ChatType::Direct)
+                        .then(|| chat_data.presence_of(&chat.name)),
+                    is_flagged: chat.flagged,
+                    is_done: chat.done,
+                    tags: chat.tags.clone(),
+                    mentions_me: chat_data.unread_mentions.contains_key(&chat.id),
+                    is_cloud_doc: chat.cloud_doc,
+                    notification: chat.notification,
+                    is_nav_pinned: chat.nav_pinned,
+                    is_marked_unread: chat.marked_unread,
+                    is_detached: chat.detached,
+                }
             })
             .collect();
+        // 置顶的在前，其余按最后活跃时间从新到旧
+        items.sort_by(|a, b| {
+            b.is_pinned
+                .cmp(&a.is_pinned)
+                .then_with(|| b.last_activity.cmp(&a.last_activity))
+        });
+
+        let mut tags: Vec<String> = chat_data
+            .chats
+            .iter()
+            .flat_map(|chat| chat.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
 
         Self {
             items,
-            ..Default::default()
+            filter,
+            tags,
         }
     }
 
 	pub fn filtered(&self) -> Vec<&ChatListItem> {
 		self.items
 			.iter()
-			.filter(|item| match self.filter {
-				ChatFilter::All => true,
-				ChatFilter::Pinned => item.is_pinned,
-			})
+			.filter(|item| self.filter.matches(item))
 			.collect()
 	}
+
+	/// 分组里的聊天数
+	pub fn count(&self, filter: ChatFilter) -> usize {
+		self.items.iter().filter(|item| filter.matches(item)).count()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(chat_type: ChatType) -> ChatListItem {
+        ChatListItem {
+            chat_type,
+            ..Default::default()
+        }
+    }
+
+    fn groups(item: &ChatListItem) -> Vec<ChatFilter> {
+        ChatFilter::GROUPS
+            .into_iter()
+            .filter(|filter| filter.matches(item))
+            .collect()
+    }
+
+    #[test]
+    fn chat_types_land_in_their_groups() {
+        assert_eq!(groups(&item(ChatType::Direct)), [ChatFilter::Direct]);
+        assert_eq!(groups(&item(ChatType::Group)), [ChatFilter::Group]);
+        // 话题群同时算作群组
+        assert_eq!(
+            groups(&item(ChatType::TopicGroup)),
+            [ChatFilter::Group, ChatFilter::Topic]
+        );
+        assert!(groups(&item(ChatType::Bot)).is_empty());
+        assert!(ChatFilter::All.matches(&item(ChatType::Announcement)));
+    }
+
+    #[test]
+    fn flags_select_their_groups() {
+        let chat = ChatListItem {
+            chat_type: ChatType::Bot,
+            is_pinned: true,
+            is_flagged: true,
+            mentions_me: true,
+            tags: vec!["周报".to_string()],
+            is_cloud_doc: true,
+            ..Default::default()
+        };
+        assert!(ChatFilter::Pinned.matches(&chat));
+        assert_eq!(
+            groups(&chat),
+            [
+                ChatFilter::Flagged,
+                ChatFilter::Mentions,
+                ChatFilter::Tagged,
+                ChatFilter::Docs
+            ]
+        );
+        assert!(!ChatFilter::Pinned.matches(&item(ChatType::Group)));
+    }
+
+    #[test]
+    fn status_expires_at_its_deadline() {
+        let now = Utc::now();
+        let status = UserStatus {
+            icon: "📅".to_string(),
+            text: "In a meeting".to_string(),
+            expires_at: Some(now + chrono::Duration::minutes(5)),
+            do_not_disturb: false,
+        };
+        assert!(!status.is_expired(now));
+        assert_eq!(status.time_left(now), Some(std::time::Duration::from_secs(300)));
+        let later = now + chrono::Duration::minutes(6);
+        assert!(status.is_expired(later));
+        assert_eq!(status.time_left(later), Some(std::time::Duration::ZERO));
+
+        let forever = UserStatus {
+            expires_at: None,
+            ..status
+        };
+        assert!(!forever.is_expired(later));
+        assert_eq!(forever.time_left(later), None);
+    }
+
+    #[test]
+    fn done_chats_only_show_under_done() {
+        let chat = ChatListItem {
+            is_done: true,
+            is_flagged: true,
+            ..item(ChatType::Group)
+        };
+        assert_eq!(groups(&chat), [ChatFilter::Done]);
+        assert!(!ChatFilter::All.matches(&chat));
+        assert!(!ChatFilter::Done.matches(&item(ChatType::Group)));
+    }
 }
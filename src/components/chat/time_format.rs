@@ -0,0 +1,56 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, Utc, Weekday};
+
+/// 转换为本地时区的日期，用来给消息按天分组
+pub fn local_date(timestamp: &DateTime<Utc>) -> NaiveDate {
+    timestamp.with_timezone(&Local).date_naive()
+}
+
+/// 日期分隔条上的文字：今天、昨天、一周内显示星期，更早显示具体日期
+pub fn format_day_label(timestamp: &DateTime<Utc>, now: &DateTime<Local>) -> String {
+    let date = local_date(timestamp);
+    let today = now.date_naive();
+    let days_ago = (today - date).num_days();
+
+    match days_ago {
+        0 => "今天".to_string(),
+        1 => "昨天".to_string(),
+        2..=6 => weekday_label(date.weekday()).to_string(),
+        _ if date.year() == today.year() => format!("{}月{}日", date.month(), date.day()),
+        _ => format!("{}年{}月{}日", date.year(), date.month(), date.day()),
+    }
+}
+
+/// 消息旁显示的时间，如 `14:05`
+pub fn format_time(timestamp: &DateTime<Utc>) -> String {
+    timestamp.with_timezone(&Local).format("%H:%M").to_string()
+}
+
+/// 聊天列表里的时间：今天的显示时分，更早的显示日期
+pub fn format_list_time(timestamp: &DateTime<Utc>, now: &DateTime<Local>) -> String {
+    if local_date(timestamp) == now.date_naive() {
+        format_time(timestamp)
+    } else {
+        format_day_label(timestamp, now)
+    }
+}
+
+/// 悬停时显示的完整时间，如 `昨天 14:05`
+pub fn format_hover_time(timestamp: &DateTime<Utc>, now: &DateTime<Local>) -> String {
+    format!(
+        "{} {}",
+        format_day_label(timestamp, now),
+        format_time(timestamp)
+    )
+}
+
+fn weekday_label(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "星期一",
+        Weekday::Tue => "星期二",
+        Weekday::Wed => "星期三",
+        Weekday::Thu => "星期四",
+        Weekday::Fri => "星期五",
+        Weekday::Sat => "星期六",
+        Weekday::Sun => "星期日",
+    }
+}
@@ -0,0 +1,373 @@
+use std::{collections::HashMap, ops::Range};
+
+use bevy_egui::egui::{vec2, Rect, Ui, UiBuilder};
+use chrono::NaiveDate;
+
+use super::{local_date, ChatMessage};
+
+/// 还没测量过的行先按这个高度估算
+const ESTIMATED_HEADER_HEIGHT: f32 = 30.0;
+const ESTIMATED_MESSAGE_HEIGHT: f32 = 56.0;
+const ESTIMATED_SEPARATOR_HEIGHT: f32 = 30.0;
+/// 可视区域上下多布局一段，快速滚动时不容易看到空白
+const OVERSCAN: f32 = 200.0;
+
+/// 时间线上的一行，`index` 指向消息列表中的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimelineRow {
+    /// 列表顶部，用来显示加载更早消息的状态
+    Header,
+    /// 日期分隔条，`index` 是当天的第一条消息
+    DaySeparator { index: usize },
+    Message { index: usize, show_avatar: bool },
+}
+
+/// 测量结果按消息 id 缓存，插入新消息后旧行的高度仍然可用
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RowKey {
+    Header,
+    Separator(String),
+    Message(String, bool),
+}
+
+/// 虚拟化的消息列表，只布局可视区域内的行，行高在第一次显示时测量
+#[derive(Debug, Default)]
+pub struct MessageTimeline {
+    chat_id: String,
+    /// 消息列表的版本号（`ChatData::revision`），变化时重新分组
+    revision: u64,
+    rows: Vec<TimelineRow>,
+    keys: Vec<RowKey>,
+    heights: Vec<f32>,
+    /// `offsets[i]` 是第 i 行的顶部位置，最后一项是总高度
+    offsets: Vec<f32>,
+    offsets_dirty: bool,
+    measured: HashMap<RowKey, f32>,
+    width: f32,
+    /// 消息 id -> 所在行，用来在插入更早的消息后找回锚点
+    message_rows: HashMap<String, usize>,
+    /// 上一帧可视区域顶部的第一条消息，以及可视区域顶部相对它的偏移
+    anchor: Option<(String, f32)>,
+    viewport_top: f32,
+    at_bottom: bool,
+    /// 下一帧要滚动到的消息
+    scroll_target: Option<String>,
+}
+
+impl MessageTimeline {
+    /// 消息列表变化时重新生成行，版本号没变时什么都不做
+    pub fn update(&mut self, chat_id: &str, messages: &[ChatMessage], revision: u64) {
+        if self.chat_id == chat_id && self.revision == revision {
+            return;
+        }
+        if self.chat_id != chat_id {
+            self.chat_id = chat_id.to_string();
+            self.measured.clear();
+            self.anchor = None;
+        }
+        self.revision = revision;
+        self.rebuild(messages);
+    }
+
+    pub fn rows(&self) -> &[TimelineRow] {
+        &self.rows
+    }
+
+    pub fn total_height(&self) -> f32 {
+        self.offsets.last().copied().unwrap_or(0.0)
+    }
+
+    /// 下一帧滚动到指定消息，消息需要已经在列表里
+    pub fn scroll_to(&mut self, message_id: &str) {
+        self.scroll_target = Some(message_id.to_string());
+    }
+
+    /// 锚点上方的行高变化或插入了更早的消息时，返回让锚点消息保持不动的滚动位置。
+    /// 停在底部时交给 `stick_to_bottom` 处理
+    pub fn scroll_correction(&mut self) -> Option<f32> {
+        self.update_offsets();
+        if let Some(row) = self
+            .scroll_target
+            .take()
+            .and_then(|id| self.message_rows.get(&id).copied())
+        {
+            // 目标消息上面留一点空间，日期分隔条也能看到
+            return Some((self.offsets[row] - ESTIMATED_SEPARATOR_HEIGHT * 2.0).max(0.0));
+        }
+        if self.at_bottom {
+            return None;
+        }
+        let (id, delta) = self.anchor.as_ref()?;
+        let row = *self.message_rows.get(id)?;
+        let top = self.offsets[row] + delta;
+        ((top - self.viewport_top).abs() > 0.5).then_some(top)
+    }
+
+    /// 在 `ScrollArea::show_viewport` 里调用，`add_row` 只会收到可视区域附近的行
+    pub fn show_rows(
+        &mut self,
+        ui: &mut Ui,
+        viewport: Rect,
+        mut add_row: impl FnMut(&mut Ui, TimelineRow),
+    ) {
+        let width = ui.available_width();
+        // 宽度变了换行也会变，之前的测量都不准了
+        if (width - self.width).abs() > 1.0 {
+            self.width = width;
+            self.measured.clear();
+            self.reset_heights();
+        }
+        self.update_offsets();
+
+        let origin = ui.max_rect().min;
+        ui.set_min_size(vec2(width, self.total_height()));
+
+        self.viewport_top = viewport.top();
+        self.at_bottom = viewport.bottom() >= self.total_height() - 1.0;
+        self.anchor = None;
+
+        let mut changed = false;
+        for row in self.visible_range(viewport.top() - OVERSCAN, viewport.bottom() + OVERSCAN) {
+            let rect = Rect::from_min_size(
+                origin + vec2(0.0, self.offsets[row]),
+                vec2(width, self.heights[row]),
+            );
+            let response = ui.allocate_new_ui(UiBuilder::new().max_rect(rect), |ui| {
+                add_row(ui, self.rows[row]);
+            });
+            // 以可视区域里第一条消息作锚点，顶部状态行和日期分隔条可能会消失
+            if let RowKey::Message(id, _) = &self.keys[row] {
+                if self.anchor.is_none() && self.offsets[row + 1] > viewport.top() {
+                    self.anchor = Some((id.clone(), viewport.top() - self.offsets[row]));
+                }
+            }
+            let height = response.response.rect.height();
+            if (height - self.heights[row]).abs() > 0.5 {
+                self.heights[row] = height;
+                self.measured.insert(self.keys[row].clone(), height);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.offsets_dirty = true;
+            ui.ctx().request_repaint();
+        }
+    }
+
+    /// 和 `[top, bottom)` 有交集的行
+    pub fn visible_range(&self, top: f32, bottom: f32) -> Range<usize> {
+        if self.rows.is_empty() {
+            return 0..0;
+        }
+        let start = self.offsets[1..].partition_point(|&row_bottom| row_bottom <= top);
+        let end = self.offsets[..self.rows.len()].partition_point(|&row_top| row_top < bottom);
+        start..end.max(start)
+    }
+
+    fn rebuild(&mut self, messages: &[ChatMessage]) {
+        self.rows.clear();
+        self.keys.clear();
+        self.message_rows.clear();
+        self.rows.push(TimelineRow::Header);
+        self.keys.push(RowKey::Header);
+
+        let mut last_date: Option<NaiveDate> = None;
+        let mut last_sender: Option<(&str, i64)> = None;
+        for (index, message) in messages.iter().enumerate() {
+            let date = local_date(&message.timestamp);
+            if last_date.is_none_or(|last| last != date) {
+                self.rows.push(TimelineRow::DaySeparator { index });
+                self.keys.push(RowKey::Separator(message.id.clone()));
+                last_date = Some(date);
+                last_sender = None;
+            }
+
+            // 同一个人一分钟内连续发的消息只显示一次头像
+            let minute = message.timestamp.timestamp() / 60;
+            let show_avatar = last_sender
+                .is_none_or(|(sender, min)| message.sender != sender || minute != min);
+            if show_avatar {
+                last_sender = Some((&message.sender, minute));
+            }
+            self.message_rows.insert(message.id.clone(), self.rows.len());
+            self.rows.push(TimelineRow::Message { index, show_avatar });
+            self.keys.push(RowKey::Message(message.id.clone(), show_avatar));
+        }
+
+        self.reset_heights();
+    }
+
+    fn reset_heights(&mut self) {
+        self.heights = self
+            .keys
+            .iter()
+            .map(|key| {
+                self.measured.get(key).copied().unwrap_or(match key {
+                    RowKey::Header => ESTIMATED_HEADER_HEIGHT,
+                    RowKey::Separator(_) => ESTIMATED_SEPARATOR_HEIGHT,
+                    RowKey::Message(..) => ESTIMATED_MESSAGE_HEIGHT,
+                })
+            })
+            .collect();
+        self.offsets_dirty = true;
+    }
+
+    fn update_offsets(&mut self) {
+        if !self.offsets_dirty {
+            return;
+        }
+        self.offsets.clear();
+        self.offsets.reserve(self.heights.len() + 1);
+        let mut top = 0.0;
+        self.offsets.push(top);
+        for height in &self.heights {
+            top += height;
+            self.offsets.push(top);
+        }
+        self.offsets_dirty = false;
+    }
+}
+
+/// 话题群里每个话题的位置和回复数，消息列表的版本号变化时重新统计
+#[derive(Debug, Default)]
+pub struct TopicIndex {
+    chat_id: String,
+    revision: u64,
+    /// 话题 id -> (话题在消息列表中的位置, 回复数)
+    topics: HashMap<String, (usize, usize)>,
+}
+
+impl TopicIndex {
+    pub fn update(&mut self, chat_id: &str, messages: &[ChatMessage], revision: u64) {
+        if self.chat_id == chat_id && self.revision == revision {
+            return;
+        }
+        self.chat_id = chat_id.to_string();
+        self.revision = revision;
+        self.topics.clear();
+        for (index, message) in messages.iter().enumerate() {
+            match &message.reply_to {
+                None => {
+                    self.topics.insert(message.id.clone(), (index, 0));
+                }
+                Some(root) => {
+                    if let Some((_, replies)) = self.topics.get_mut(root) {
+                        *replies += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 话题第一条消息在列表中的位置
+    pub fn position(&self, topic_id: &str) -> Option<usize> {
+        self.topics.get(topic_id).map(|(index, _)| *index)
+    }
+
+    pub fn replies(&self, topic_id: &str) -> usize {
+        self.topics.get(topic_id).map_or(0, |(_, replies)| *replies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn message(id: &str, sender: &str, timestamp: DateTime<Utc>) -> ChatMessage {
+        ChatMessage {
+            id: id.to_string(),
+            sender: sender.to_string(),
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    /// 顶部状态行、日期分隔条和三条同一个人连续发的消息，都还没测量过
+    fn timeline() -> (MessageTimeline, Vec<ChatMessage>) {
+        let start = "2024-06-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let messages = vec![
+            message("1", "Alice", start),
+            message("2", "Alice", start + Duration::seconds(10)),
+            message("3", "Alice", start + Duration::seconds(20)),
+        ];
+        let mut timeline = MessageTimeline::default();
+        timeline.update("c", &messages, 1);
+        timeline.update_offsets();
+        (timeline, messages)
+    }
+
+    #[test]
+    fn groups_messages_under_one_avatar() {
+        let (timeline, _) = timeline();
+        assert_eq!(
+            timeline.rows(),
+            &[
+                TimelineRow::Header,
+                TimelineRow::DaySeparator { index: 0 },
+                TimelineRow::Message { index: 0, show_avatar: true },
+                TimelineRow::Message { index: 1, show_avatar: false },
+                TimelineRow::Message { index: 2, show_avatar: false },
+            ]
+        );
+        assert_eq!(timeline.total_height(), 30.0 + 30.0 + 56.0 * 3.0);
+    }
+
+    #[test]
+    fn visible_range_covers_rows_intersecting_the_viewport() {
+        let (timeline, _) = timeline();
+        // 行的顶部依次是 0、30、60、116、172
+        assert_eq!(timeline.visible_range(0.0, 10.0), 0..1);
+        assert_eq!(timeline.visible_range(30.0, 60.0), 1..2);
+        assert_eq!(timeline.visible_range(59.0, 61.0), 1..3);
+        assert_eq!(timeline.visible_range(120.0, 130.0), 3..4);
+        assert_eq!(timeline.visible_range(-100.0, 1000.0), 0..5);
+        assert!(timeline.visible_range(1000.0, 2000.0).is_empty());
+        assert_eq!(MessageTimeline::default().visible_range(0.0, 100.0), 0..0);
+    }
+
+    #[test]
+    fn rebuilds_only_when_the_revision_changes() {
+        let (mut timeline, mut messages) = timeline();
+        messages[1].sender = "Bob".to_string();
+        timeline.update("c", &messages, 1);
+        assert_eq!(
+            timeline.rows()[3],
+            TimelineRow::Message { index: 1, show_avatar: false }
+        );
+
+        timeline.update("c", &messages, 2);
+        assert_eq!(
+            timeline.rows()[3],
+            TimelineRow::Message { index: 1, show_avatar: true }
+        );
+    }
+
+    #[test]
+    fn topic_index_counts_replies_per_revision() {
+        let now = Utc::now();
+        let mut messages = vec![
+            message("t1", "Alice", now),
+            message("t2", "Bob", now),
+            ChatMessage {
+                reply_to: Some("t1".to_string()),
+                ..message("r1", "Carol", now)
+            },
+        ];
+        let mut topics = TopicIndex::default();
+        topics.update("c", &messages, 1);
+        assert_eq!(topics.position("t2"), Some(1));
+        assert_eq!(topics.replies("t1"), 1);
+        assert_eq!(topics.position("r1"), None);
+
+        messages.push(ChatMessage {
+            reply_to: Some("t1".to_string()),
+            ..message("r2", "Bob", now)
+        });
+        topics.update("c", &messages, 1);
+        assert_eq!(topics.replies("t1"), 1);
+        topics.update("c", &messages, 2);
+        assert_eq!(topics.replies("t1"), 2);
+    }
+}
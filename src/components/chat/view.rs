@@ -1,4 +1,7 @@
-use super::{controller::ChatListController, ChatEvent, ChatListItem, AVATAR_COLORS};
+use super::{
+    controller::ChatListController, presence_color, render_type_badge, ChatAction, ChatEvent,
+    ChatListItem, NotificationLevel, AVATAR_COLORS,
+};
 use crate::resources::NotificationTheme;
 use bevy_egui::egui::{self, Color32, Margin};
 
@@ -105,33 +108,74 @@ impl<'a> ChatListView<'a> {
 
                     ui.ctx().set_visuals(visuals);
 
+                    let has_unread = item.unread_count.is_some_and(|count| count > 0);
+                    // 文字随当前状态切换
                     let menu_items = [
-                        "置顶",
-                        "标为未读",
-                        "标记",
-                        "新建标签",
-                        "消息免打扰",
-                        "完成",
-                        "在导航栏打开",
-                        "在独立窗口打开",
+                        (
+                            if item.is_pinned { "取消置顶" } else { "置顶" },
+                            ChatAction::TogglePin,
+                        ),
+                        (
+                            if has_unread || item.is_marked_unread {
+                                "标为已读"
+                            } else {
+                                "标为未读"
+                            },
+                            ChatAction::ToggleUnread,
+                        ),
+                        (
+                            if item.is_flagged { "取消标记" } else { "标记" },
+                            ChatAction::ToggleFlag,
+                        ),
+                    ];
+                    let more_items = [
+                        (
+                            if item.is_done { "取消完成" } else { "完成" },
+                            ChatAction::ToggleDone,
+                        ),
+                        (
+                            if item.is_nav_pinned {
+                                "从导航栏移除"
+                            } else {
+                                "在导航栏打开"
+                            },
+                            ChatAction::ToggleNavPin,
+                        ),
+                        (
+                            if item.is_detached {
+                                "关闭独立窗口"
+                            } else {
+                                "在独立窗口打开"
+                            },
+                            ChatAction::ToggleWindow,
+                        ),
                     ];
 
-                    for menu_item in menu_items {
-                        let text = egui::RichText::new(menu_item)
-                            .font(theme.fonts.content.clone())
-                            .color(theme.text_styles.chat_message.color);
-
-                        if ui
-                            .add(
-                                egui::Button::new(text)
-                                    .frame(false)
-                                    .fill(egui::Color32::TRANSPARENT),
-                            )
-                            .clicked()
-                        {
-                            ui.close_menu();
+                    let mut chosen = None;
+                    for (label, action) in menu_items {
+                        if self.render_menu_button(ui, label, theme).clicked() {
+                            chosen = Some(action);
+                        }
+                    }
+                    if let Some(action) = self.render_tag_menu(ui, item, theme) {
+                        chosen = Some(action);
+                    }
+                    if let Some(action) = self.render_notification_menu(ui, item, theme) {
+                        chosen = Some(action);
+                    }
+                    for (label, action) in more_items {
+                        if self.render_menu_button(ui, label, theme).clicked() {
+                            chosen = Some(action);
                         }
                     }
+
+                    if let Some(action) = chosen {
+                        event = Some(ChatEvent::Action {
+                            id: item.id.clone(),
+                            action,
+                        });
+                        ui.close_menu();
+                    }
                 });
 
                 if response.clicked() {
@@ -142,6 +186,87 @@ impl<'a> ChatListView<'a> {
         event
     }
 
+    fn render_menu_button(
+        &self,
+        ui: &mut egui::Ui,
+        label: &str,
+        theme: &NotificationTheme,
+    ) -> egui::Response {
+        let text = egui::RichText::new(label)
+            .font(theme.fonts.content.clone())
+            .color(theme.text_styles.chat_message.color);
+        ui.add(
+            egui::Button::new(text)
+                .frame(false)
+                .fill(egui::Color32::TRANSPARENT),
+        )
+    }
+
+    /// 通知级别子菜单
+    fn render_notification_menu(
+        &self,
+        ui: &mut egui::Ui,
+        item: &ChatListItem,
+        theme: &NotificationTheme,
+    ) -> Option<ChatAction> {
+        let mut chosen = None;
+        let text = egui::RichText::new("消息免打扰")
+            .font(theme.fonts.content.clone())
+            .color(theme.text_styles.chat_message.color);
+        ui.menu_button(text, |ui| {
+            for level in NotificationLevel::ALL {
+                if ui
+                    .radio(item.notification == level, level.label())
+                    .clicked()
+                {
+                    chosen = Some(ChatAction::SetNotification(level));
+                    ui.close_menu();
+                }
+            }
+        });
+        chosen
+    }
+
+    /// 标签子菜单：勾选已有标签，或者输入名字新建
+    fn render_tag_menu(
+        &self,
+        ui: &mut egui::Ui,
+        item: &ChatListItem,
+        theme: &NotificationTheme,
+    ) -> Option<ChatAction> {
+        let mut chosen = None;
+        let text = egui::RichText::new("新建标签")
+            .font(theme.fonts.content.clone())
+            .color(theme.text_styles.chat_message.color);
+        ui.menu_button(text, |ui| {
+            for tag in &self.controller.view().tags {
+                let mut checked = item.tags.contains(tag);
+                if ui.checkbox(&mut checked, tag).clicked() {
+                    chosen = Some(ChatAction::ToggleTag(tag.clone()));
+                }
+            }
+            if !self.controller.view().tags.is_empty() {
+                ui.separator();
+            }
+
+            // 输入中的新标签名放在 egui 的临时存储里
+            let id = ui.make_persistent_id(("new_tag", &item.id));
+            let mut name = ui.data_mut(|data| data.get_temp::<String>(id).unwrap_or_default());
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut name)
+                    .desired_width(120.0)
+                    .hint_text("标签名"),
+            );
+            let submit = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if (ui.button("添加").clicked() || submit) && !name.trim().is_empty() {
+                chosen = Some(ChatAction::ToggleTag(name.trim().to_string()));
+                name.clear();
+            }
+            ui.data_mut(|data| data.insert_temp(id, name));
+        });
+        chosen
+    }
+
     fn get_avatar_color(&self, avatar: &str) -> Color32 {
         let index = avatar.bytes().fold(0usize, |acc, b| {
             acc.wrapping_add(b as usize) % AVATAR_COLORS.len()
@@ -170,8 +295,30 @@ impl<'a> ChatListView<'a> {
                 .min_size(avatar_size),
             );
 
-            // 渲染未读计数标记
-            if let Some(count) = item.unread_count {
+            // 单聊在头像右下角显示对方的在线状态
+            if let Some(presence) = item.presence {
+                let center = avatar_response.rect.right_bottom() - egui::vec2(5.0, 5.0);
+                ui.painter()
+                    .circle_filled(center, 5.0, theme.current_colors().background);
+                ui.painter()
+                    .circle_filled(center, 4.0, presence_color(presence, theme));
+            }
+
+            // 渲染未读计数标记。手动标为未读的只显示一个点，免打扰的显示灰点不显示数字
+            let unread = item.unread_count.unwrap_or(0);
+            let notifies = item.notification.notifies(item.mentions_me);
+            if (unread == 0 && item.is_marked_unread) || (unread > 0 && !notifies) {
+                ui.painter().circle_filled(
+                    BadgePosition::DEFAULT.calculate_pos(avatar_response.rect),
+                    5.0,
+                    if notifies {
+                        theme.text_styles.chat_unread.color
+                    } else {
+                        theme.text_styles.chat_time.color
+                    },
+                );
+            }
+            if let Some(count) = item.unread_count.filter(|_| notifies) {
                 if count > 0 {
                     let badge_size = 16.0;
                     let badge_pos = BadgePosition::DEFAULT.calculate_pos(avatar_response.rect);
@@ -213,15 +360,20 @@ impl<'a> ChatListView<'a> {
                     .color(theme.text_styles.chat_title.color)
                     .strong(),
             );
+            if let Some(badge) = item.chat_type.badge() {
+                render_type_badge(ui, badge, theme);
+            }
 
             // 时间戳
-            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                ui.label(
-                    egui::RichText::new("12:22")
-                        .font(theme.fonts.timestamp.clone())
-                        .color(theme.text_styles.chat_time.color),
-                );
-            });
+            if let Some(time) = &item.last_time {
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(
+                        egui::RichText::new(time)
+                            .font(theme.fonts.timestamp.clone())
+                            .color(theme.text_styles.chat_time.color),
+                    );
+                });
+            }
         });
 
         // 最后一条消息
@@ -236,11 +388,21 @@ impl<'a> ChatListView<'a> {
                 if response.hovered() {
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         // 已读标记
-                        ui.label(
-                            egui::RichText::new("已读")
-                                .font(theme.fonts.icon.clone())
-                                .color(theme.text_styles.chat_message.color),
-                        );
+                        if let Some(read_label) = &item.read_label {
+                            ui.label(
+                                egui::RichText::new(read_label)
+                                    .font(theme.fonts.icon.clone())
+                                    .color(theme.text_styles.chat_message.color),
+                            );
+                        }
+
+                        if item.notification.is_muted() {
+                            ui.label(
+                                egui::RichText::new("🔕")
+                                    .font(theme.fonts.icon.clone())
+                                    .color(theme.text_styles.chat_message.color),
+                            );
+                        }
 
                         // 置顶标记
                         if item.is_pinned {
@@ -1,7 +1,8 @@
 use bevy::{prelude::ResMut, text};
 use bevy_egui::egui::{self, menu};
+use chrono::Local;
 
-use crate::resources::UiState;
+use crate::resources::{StatusDraft, StatusDuration, UiState, STATUS_PRESETS};
 
 struct WindowConf {
     width: f32,
@@ -57,6 +58,21 @@ pub fn avatar(ui: &mut egui::Ui, ui_state: &mut ResMut<UiState>) {
             .rounding(30.0)
             .min_size(egui::vec2(32.0, 32.0)),
         );
+        // 状态图标画在头像右下角
+        let avatar_response = match &ui_state.user_status {
+            Some(status) => {
+                ui.painter().text(
+                    avatar_response.rect.right_bottom(),
+                    egui::Align2::CENTER_CENTER,
+                    &status.icon,
+                    egui::FontId::proportional(12.0),
+                    egui::Color32::WHITE,
+                );
+                let hover = format!("{} · {}", status.text, status.expiry_label(&Local::now()));
+                avatar_response.on_hover_text(hover)
+            }
+            None => avatar_response,
+        };
         if avatar_response.clicked() {
             ui_state.show_avatar_menu = !ui_state.show_avatar_menu;
         }
@@ -111,7 +127,11 @@ pub fn show_avatar_menu(
                 ui.heading("R");
                 ui.label("Lark personal account");
                 ui.add_space(4.0);
-                let status_button = ui.button("+ Status");
+                let status_text = match &ui_state.user_status {
+                    Some(status) => format!("{} {}", status.icon, status.text),
+                    None => "+ Status".to_string(),
+                };
+                let status_button = ui.button(status_text);
                 if status_button.clicked() {
                     ui_state.show_status_menu = !ui_state.show_status_menu;
                 }
@@ -180,16 +200,14 @@ struct StatusMenuItem<'a> {
     text: &'a str,
     duration: &'a str,
 }
-fn render_status_menu_item(ui: &mut egui::Ui, item: &StatusMenuItem) {
+fn render_status_menu_item(ui: &mut egui::Ui, item: &StatusMenuItem) -> egui::Response {
     ui.add_space(8.0);
     let resp = ui.add_sized(
         egui::vec2(ui.available_width(), 50.0),
         egui::Button::new("").fill(egui::Color32::TRANSPARENT),
     );
-    if resp.clicked() {
-        println!("status menu item clicked: {}", item.text);
-    }
     status_style(ui, item.icon, item.text, item.duration, resp.rect);
+    resp
 }
 
 pub fn show_status_menu(
@@ -197,7 +215,6 @@ pub fn show_status_menu(
     button_rect: egui::Rect,
     ui_state: &mut ResMut<UiState>,
 ) {
-    let _ = ui_state;
     let menu_width = 280.0;
 
     let menu_pos = egui::pos2(button_rect.right() + 10.0, button_rect.top());
@@ -225,46 +242,92 @@ pub fn show_status_menu(
             ui.set_min_width(menu_width);
             ui.heading("My Status");
             ui.add_space(8.0);
-            // TODO: replace icon
-            let status_items = vec![
-                StatusMenuItem {
-                    icon: "🔕",
-                    text: "Do Not Disturb",
-                    duration: "Until 5:00 PM",
-                },
-                StatusMenuItem {
-                    icon: "📅",
-                    text: "In a meeting",
-                    duration: "1 hour",
-                },
-                StatusMenuItem {
-                    icon: "🏠",
-                    text: "Working from home",
-                    duration: "All day",
-                },
-            ];
-            for item in status_items {
-                render_status_menu_item(ui, &item);
+            let now = Local::now();
+            if let Some(status) = ui_state.user_status.clone() {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(&status.icon).size(17.0));
+                    ui.vertical(|ui| {
+                        ui.label(egui::RichText::new(&status.text).size(16.0).strong());
+                        ui.label(
+                            egui::RichText::new(status.expiry_label(&now))
+                                .size(14.0)
+                                .color(egui::Color32::GRAY),
+                        );
+                    });
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Clear").clicked() {
+                            ui_state.set_user_status(None);
+                        }
+                    });
+                });
+                ui.separator();
+            }
+            for preset in STATUS_PRESETS {
+                let duration = preset.duration.label();
+                let item = StatusMenuItem {
+                    icon: preset.icon,
+                    text: preset.text,
+                    duration: &duration,
+                };
+                if render_status_menu_item(ui, &item).clicked() {
+                    ui_state.set_user_status(Some(preset.to_status(now)));
+                    ui_state.show_status_menu = false;
+                }
             }
             ui.add_space(8.0);
             ui.separator();
             ui.add_space(8.0);
+            if ui_state.status_draft.open {
+                render_status_form(ui, ui_state);
+                ui.add_space(8.0);
+            }
             ui.horizontal(|ui| {
                 if ui
                     .button(egui::RichText::new("+ Add status").size(14.0))
                     .clicked()
                 {
-                    println!("add status clicked");
+                    ui_state.status_draft.open = !ui_state.status_draft.open;
                 }
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    if ui.button(egui::RichText::new("⚙").size(14.0)).clicked() {
-                        println!("status settings clicked");
+                    let settings = ui
+                        .button(egui::RichText::new("⚙").size(14.0))
+                        .on_hover_text("Edit status");
+                    if settings.clicked() {
+                        ui_state.status_draft = StatusDraft::edit(ui_state.user_status.as_ref());
                     }
                 })
             })
         });
 }
 
+/// 自定义状态：图标、文字和持续时间
+fn render_status_form(ui: &mut egui::Ui, ui_state: &mut ResMut<UiState>) {
+    let draft = &mut ui_state.status_draft;
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut draft.icon)
+                .hint_text("💬")
+                .desired_width(28.0),
+        );
+        ui.add(egui::TextEdit::singleline(&mut draft.text).hint_text("What's your status?"));
+    });
+    egui::ComboBox::from_id_salt("status_duration")
+        .selected_text(draft.duration.label())
+        .show_ui(ui, |ui| {
+            for duration in StatusDuration::CHOICES {
+                ui.selectable_value(&mut draft.duration, duration, duration.label());
+            }
+        });
+    ui.checkbox(&mut draft.do_not_disturb, "Pause notifications");
+    let save = ui.add_enabled(!draft.text.trim().is_empty(), egui::Button::new("Save"));
+    if save.clicked() {
+        let status = draft.to_status(Local::now());
+        ui_state.status_draft = Default::default();
+        ui_state.set_user_status(status);
+        ui_state.show_status_menu = false;
+    }
+}
+
 fn status_style(ui: &mut egui::Ui, icon: &str, text: &str, duration: &str, resp: egui::Rect) {
 	ui.allocate_ui_at_rect(resp, |ui| {
 		ui.horizontal(|ui| {
@@ -1,11 +1,9 @@
-use std::collections::HashMap;
-
 use bevy::prelude::ResMut;
 use bevy_egui::egui::{self, Frame, RichText};
 
 use crate::{
     resources::{NotificationTheme, UiState},
-    ChatEvent, ChatFilter, ChatListController, ChatListView,
+    ChatEvent, ChatFilter, ChatListController, ChatListModel, ChatListView,
 };
 
 pub fn left_chat_list_ui(
@@ -13,16 +11,13 @@ pub fn left_chat_list_ui(
     ui_state: &mut ResMut<UiState>,
     theme: &mut ResMut<NotificationTheme>,
 ) -> egui::InnerResponse<()> {
-    let unread_counts: HashMap<String, i32> = ui_state
-        .unread_counts
-        .iter()
-        .map(|(k, v)| (k.clone(), *v))
-        .collect();
-
-    let mut controller =
-        ChatListController::new(&ui_state.chats, &ui_state.select_chat_id, &unread_counts);
+    let mut controller = ChatListController::new(
+        &ui_state.chat_data,
+        &ui_state.select_chat_id,
+        ui_state.chat_filter,
+    );
 
-    let chats = ui_state.chats.clone();
+    let chats = ui_state.chat_data.chats.clone();
     let colors = theme.current_colors();
     let frame = Frame {
         fill: colors.background,
@@ -75,20 +70,12 @@ pub fn left_chat_list_ui(
             match view.render(ui, theme) {
                 ChatEvent::Selected { id } => {
                     if let Some(_chat) = chats.iter().find(|c| c.id == id) {
-                        ui_state.select_chat_id = id.clone();
-                        let chat_messages = ui_state
-                            .messages
-                            .iter()
-                            .filter(|msg| msg.chat_id == id)
-                            .cloned()
-                            .collect::<Vec<_>>();
-                        ui_state.messages = chat_messages;
-                        // 标记为已读
-                        if let Some(count) = ui_state.unread_counts.get_mut(&id) {
-                            *count = 0;
-                        }
+                        ui_state.select_chat(&id);
                     }
                 }
+                ChatEvent::Action { id, action } => {
+                    ui_state.apply_chat_action(&id, action);
+                }
                 ChatEvent::None => {}
             }
 
@@ -108,6 +95,11 @@ pub fn left_sidebar_ui(
     theme: &NotificationTheme,
 ) -> egui::InnerResponse<()> {
     let colors = theme.current_colors();
+    let model = ChatListModel::new(
+        &ui_state.chat_data,
+        &ui_state.select_chat_id,
+        ui_state.chat_filter,
+    );
     egui::SidePanel::left("left_sidebar_ui")
         .resizable(true)
         .max_width(180.0)
@@ -126,26 +118,24 @@ pub fn left_sidebar_ui(
                         .color(theme.text_styles.title.color),
                 );
                 ui.add_space(10.0);
-                let menu_items = [
-                    ("标记", 2),
-                    ("@我", 2),
-                    ("标签", 2),
-                    ("单聊", 2),
-                    ("群组", 2),
-                    ("云文档", 2),
-                    ("话题", 2),
-                    ("已完成", 2),
-                ];
-                for (label, count) in menu_items {
+                for filter in ChatFilter::GROUPS {
                     ui.horizontal(|ui| {
                         ui.add_space(10.0);
-                        let is_selected = ui_state.selected_siderbar_button == label;
-                        let response = ui.selectable_label(is_selected, label);
+                        let is_selected = ui_state.chat_filter == filter;
+                        let response = ui.selectable_label(is_selected, filter.label());
                         ui.add_space(ui.available_width() - 45.0);
-                        ui.label(count.to_string());
+                        ui.label(
+                            RichText::new(model.count(filter).to_string())
+                                .color(theme.text_styles.chat_time.color),
+                        );
 
+                        // 再点一次回到全部
                         if response.clicked() {
-                            ui_state.selected_siderbar_button = label.to_string();
+                            ui_state.chat_filter = if is_selected {
+                                ChatFilter::All
+                            } else {
+                                filter
+                            };
                         }
                         ui.add_space(10.0);
                     });
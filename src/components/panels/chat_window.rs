@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    prelude::{default, Camera, Camera2dBundle, Commands, Component, Entity, Local, Query, ResMut},
+    render::camera::RenderTarget,
+    window::{Window, WindowRef},
+};
+use bevy_egui::EguiContexts;
+
+use crate::{
+    resources::{setup_context, ChatViewState, NotificationTheme, UiState},
+    ChatAction, ChatMainView,
+};
+
+/// 在独立窗口里打开的聊天，挂在窗口实体上
+#[derive(Component)]
+pub struct ChatWindow {
+    view: ChatViewState,
+    /// 新窗口的 egui 上下文要先设置字体和样式
+    styled: bool,
+}
+
+/// 按聊天的 `detached` 打开或关闭窗口。启动时会把上次没关的窗口重新打开
+pub fn sync_chat_windows(
+    mut commands: Commands,
+    mut ui_state: ResMut<UiState>,
+    windows: Query<(Entity, &ChatWindow)>,
+    mut opened: Local<HashMap<String, (Entity, Entity)>>,
+) {
+    // 用户直接关掉的窗口已经被 bevy 移除，只剩下相机
+    let closed: Vec<String> = opened
+        .iter()
+        .filter(|(_, (window, _))| !windows.contains(*window))
+        .map(|(chat_id, _)| chat_id.clone())
+        .collect();
+    for chat_id in closed {
+        if let Some((_, camera)) = opened.remove(&chat_id) {
+            commands.entity(camera).despawn();
+        }
+        if ui_state.chat_data.chat(&chat_id).is_some_and(|chat| chat.detached) {
+            ui_state.apply_chat_action(&chat_id, ChatAction::ToggleWindow);
+        }
+    }
+
+    let detached: Vec<(String, String)> = ui_state
+        .chat_data
+        .chats
+        .iter()
+        .filter(|chat| chat.detached)
+        .map(|chat| (chat.id.clone(), chat.name.clone()))
+        .collect();
+
+    opened.retain(|chat_id, (window, camera)| {
+        let keep = detached.iter().any(|(id, _)| id == chat_id);
+        if !keep {
+            commands.entity(*window).despawn();
+            commands.entity(*camera).despawn();
+        }
+        keep
+    });
+
+    for (chat_id, name) in detached {
+        if opened.contains_key(&chat_id) {
+            continue;
+        }
+        ui_state.ensure_messages_loaded(&chat_id);
+        let window = commands
+            .spawn(Window {
+                title: name,
+                resolution: (560., 720.).into(),
+                ..default()
+            })
+            .id();
+        let camera = commands
+            .spawn(Camera2dBundle {
+                camera: Camera {
+                    target: RenderTarget::Window(WindowRef::Entity(window)),
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        commands.entity(window).insert(ChatWindow {
+            view: ChatViewState::new(&chat_id),
+            styled: false,
+        });
+        opened.insert(chat_id, (window, camera));
+    }
+}
+
+/// 每个独立窗口用自己的 egui 上下文渲染 `ChatMainView`，消息和主窗口共用同一份数据
+pub fn chat_window_ui(
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut theme: ResMut<NotificationTheme>,
+    mut windows: Query<(Entity, &Window, &mut ChatWindow)>,
+) {
+    let view = ChatMainView::new();
+    let mut focused = HashSet::new();
+    for (entity, window, mut chat_window) in windows.iter_mut() {
+        let Some(ctx) = contexts.try_ctx_for_entity_mut(entity) else {
+            continue;
+        };
+        let ctx = ctx.clone();
+        if !chat_window.styled {
+            setup_context(&ctx);
+            chat_window.styled = true;
+        }
+
+        let chat_id = chat_window.view.chat_id.clone();
+        // 窗口在前台时，新消息算作已读
+        if window.focused {
+            focused.insert(chat_id.clone());
+        }
+        let has_unread = ui_state
+            .chat_data
+            .unread_counts
+            .get(&chat_id)
+            .is_some_and(|count| *count > 0);
+        if window.focused && has_unread {
+            ui_state.mark_as_read(&chat_id);
+        }
+
+        ui_state.swap_view(&mut chat_window.view);
+        view.render_window(&ctx, &mut ui_state, &mut theme);
+        ui_state.swap_view(&mut chat_window.view);
+    }
+    ui_state.focused_windows = focused;
+}
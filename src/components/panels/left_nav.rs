@@ -1,5 +1,8 @@
 use super::{avatar, windows::windows_button};
-use crate::resources::{NotificationTheme, ThemeMode, UiState};
+use crate::{
+    resources::{NotificationTheme, ThemeMode, UiState},
+    AVATAR_COLORS,
+};
 use bevy::prelude::{Entity, Query, ResMut};
 use bevy::window::Window;
 use bevy::winit::WinitWindows;
@@ -9,7 +12,10 @@ use bevy_egui::egui::{self};
 struct NavItem {
     icon: &'static str,
     label: &'static str,
-    has_notification: bool,
+    /// 图标右上角的未读数，0 不显示
+    notification_count: i32,
+    /// 免打扰时只画灰点，不显示数字
+    muted: bool,
 }
 
 pub fn left_nav_ui(
@@ -47,41 +53,52 @@ pub fn left_nav_ui(
             avatar(ui, ui_state);
             ui.add_space(10.0);
 
+            // 免打扰的聊天不计入
+            let unread_total = ui_state.chat_data.notifying_unread_total();
+            let do_not_disturb = ui_state.is_do_not_disturb();
+
             let nav_items = [
                 NavItem {
                     icon: "\u{e71a}",
                     label: "",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 },
                 NavItem {
                     icon: "\u{ebb4}",
                     label: "消 息",
-                    has_notification: true,
+                    notification_count: unread_total,
+                    muted: do_not_disturb,
                 },
                 NavItem {
                     icon: "\u{eb2b}",
                     label: "日 历",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 },
                 NavItem {
                     icon: "\u{ebb6}",
                     label: "文档",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 },
                 NavItem {
                     icon: "\u{e80c}",
                     label: "会议",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 },
                 NavItem {
                     icon: "\u{e6a8}",
                     label: "表格",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 },
                 NavItem {
                     icon: "\u{ebb3}",
                     label: "联系人",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 },
             ];
             for (index, item) in nav_items.iter().enumerate() {
@@ -110,9 +127,15 @@ pub fn left_nav_ui(
                 }
                 ui.add_space(10.0);
             }
+
+            render_pinned_chats(ui, ui_state, &theme);
+
             ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
                 ui.add_space(10.0);
 
+                render_notification_bell(ui, ui_state, &theme);
+                ui.add_space(10.0);
+
                 let theme_icon = if matches!(theme.mode, ThemeMode::Light) {
                     "\u{e6ed}"
                 } else {
@@ -122,7 +145,8 @@ pub fn left_nav_ui(
                 let theme_nav_item = NavItem {
                     icon: theme_icon,
                     label: "",
-                    has_notification: false,
+                    notification_count: 0,
+                    muted: false,
                 };
 
                 let theme_resp = render_nav_item(
@@ -141,6 +165,76 @@ pub fn left_nav_ui(
         })
 }
 
+/// 右键菜单里“在导航栏打开”的聊天，点击直接跳到这个聊天
+fn render_pinned_chats(
+    ui: &mut egui::Ui,
+    ui_state: &mut ResMut<UiState>,
+    theme: &NotificationTheme,
+) {
+    let pinned: Vec<(String, String, String)> = ui_state
+        .chat_data
+        .chats
+        .iter()
+        .filter(|chat| chat.nav_pinned)
+        .map(|chat| (chat.id.clone(), chat.name.clone(), chat.avatar.clone()))
+        .collect();
+    if pinned.is_empty() {
+        return;
+    }
+    ui.separator();
+    for (id, name, avatar) in pinned {
+        ui.vertical_centered(|ui| {
+            let index = avatar.bytes().fold(0usize, |acc, b| acc.wrapping_add(b as usize));
+            let response = ui
+                .add(
+                    egui::Button::new(
+                        egui::RichText::new(&avatar)
+                            .color(egui::Color32::WHITE)
+                            .strong(),
+                    )
+                    .rounding(16.0)
+                    .fill(AVATAR_COLORS[index % AVATAR_COLORS.len()])
+                    .min_size(egui::vec2(32.0, 32.0)),
+                )
+                .on_hover_text(&name);
+            if response.clicked() {
+                ui_state.selected_nav_index = 1;
+                ui_state.select_chat(&id);
+            }
+            // 和列表里的未读数一致，免打扰的不提醒
+            if ui_state.chat_data.notifying_unread(&id) > 0 {
+                ui.painter().circle_filled(
+                    response.rect.right_top(),
+                    4.0,
+                    theme.text_styles.nav_notification.color,
+                );
+            }
+        });
+        ui.add_space(6.0);
+    }
+}
+
+/// 打开通知中心，右上角显示未读通知数
+fn render_notification_bell(
+    ui: &mut egui::Ui,
+    ui_state: &mut ResMut<UiState>,
+    theme: &NotificationTheme,
+) {
+    let response = ui
+        .add(egui::Button::new(egui::RichText::new("🔔").size(20.0)).frame(false))
+        .on_hover_text("通知");
+    if response.clicked() {
+        ui_state.notifications.show_panel = !ui_state.notifications.show_panel;
+    }
+    if ui_state.notifications.unread_count() > 0 {
+        ui.painter().circle_filled(
+            response.rect.right_top(),
+            4.0,
+            theme.text_styles.nav_notification.color,
+        );
+    }
+}
+
 struct NavItemStyle<'a> {
     ctx: &'a egui::Context,
     theme: &'a NotificationTheme,
@@ -244,11 +338,27 @@ fn render_nav_item(
     };
     style.paint_hover_effect(ui, &response);
 
-    if item.has_notification {
+    if item.notification_count > 0 && item.muted {
         ui.painter().circle_filled(
-            response.rect.right_top() - egui::vec2(4., -4.),
+            response.rect.right_top() - egui::vec2(6., -6.),
             4.,
-            theme.text_styles.nav_notification.color,
+            egui::Color32::GRAY,
+        );
+    } else if item.notification_count > 0 {
+        let center = response.rect.right_top() - egui::vec2(6., -6.);
+        ui.painter()
+            .circle_filled(center, 8., theme.text_styles.nav_notification.color);
+        let count = if item.notification_count > 99 {
+            "99+".to_string()
+        } else {
+            item.notification_count.to_string()
+        };
+        ui.painter().text(
+            center,
+            egui::Align2::CENTER_CENTER,
+            count,
+            theme.fonts.label.clone(),
+            theme.text_styles.count.color,
         );
     }
 
@@ -277,6 +387,10 @@ fn render_search_nav_item(
                     .desired_width(ui.available_width() - 30.0)
                     .hint_text("搜索..."),
             );
+            // 输入内容后切到搜索页显示结果
+            if search_response.changed() && !ui_state.search_text.trim().is_empty() {
+                ui_state.selected_nav_index = 0;
+            }
             icon_response.union(search_response)
         })
         .inner
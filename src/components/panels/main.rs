@@ -1,4 +1,7 @@
-use super::{chat_main_ui, left_chat_list_ui, left_nav_ui, left_sidebar_ui};
+use super::{
+    chat_main_ui, left_chat_list_ui, left_nav_ui, left_sidebar_ui, notification_center_ui,
+    notification_toasts, search_ui,
+};
 use crate::resources::{NavPage, NotificationTheme, OccupiedScreenSpace, UiState};
 use bevy::prelude::{Entity, NonSend, Query, ResMut};
 use bevy::window::Window;
@@ -64,7 +67,7 @@ pub fn main_ui_system(
 			show_table_ui(ctx);
 		}
 		NavPage::Search => {
-			show_search_ui(ctx);
+			search_ui(ctx, &mut ui_state, &theme);
 		}
 		NavPage::Contact => {
 			show_contact_ui(ctx);
@@ -73,6 +76,8 @@ pub fn main_ui_system(
 			show_video_meeting_ui(ctx);
 		}
 	}
+	notification_center_ui(ctx, &mut ui_state, &theme);
+	notification_toasts(ctx, &mut ui_state, &theme);
 }
 
 fn show_calendar_ui(ctx: &egui::Context) {
@@ -85,16 +90,6 @@ fn show_calendar_ui(ctx: &egui::Context) {
 			ui.heading("calendar");
 		});
 }
-fn show_search_ui(ctx: &egui::Context) {
-	CentralPanel::default()
-		.frame(egui::Frame {
-			fill: egui::Color32::from_rgb(0, 0, 0),
-			..Default::default()
-		})
-		.show(ctx, |ui| {
-			ui.heading("search");
-		});
-}
 fn show_doc_ui(ctx: &egui::Context) {
 	CentralPanel::default()
 		.frame(egui::Frame {
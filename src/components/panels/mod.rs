@@ -1,12 +1,18 @@
 mod avatar;
 mod chat_list;
 mod chat_main;
+mod chat_window;
 mod left_nav;
 mod main;
+mod notifications;
+mod search;
 mod windows;
 
 pub use avatar::avatar;
 pub use chat_list::{left_chat_list_ui, left_sidebar_ui};
 pub use chat_main::chat_main_ui;
+pub use chat_window::{chat_window_ui, sync_chat_windows};
 pub use left_nav::left_nav_ui;
 pub use main::main_ui_system;
+pub use notifications::{notification_center_ui, notification_toasts};
+pub use search::search_ui;
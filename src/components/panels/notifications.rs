@@ -0,0 +1,186 @@
+use bevy::prelude::ResMut;
+use bevy_egui::egui::{self, RichText};
+use chrono::{Local, Utc};
+
+use crate::{
+    format_list_time,
+    resources::{Notification, NotificationTheme, UiState},
+};
+
+const TOAST_WIDTH: f32 = 300.0;
+
+/// 右下角弹出的新消息提醒，点击跳到对应消息
+pub fn notification_toasts(
+    ctx: &egui::Context,
+    ui_state: &mut ResMut<UiState>,
+    theme: &NotificationTheme,
+) {
+    // 到期前事件循环可能在休眠，按最近的到期时间唤醒
+    if let Some(next) = ui_state.notifications.expire_toasts(Utc::now()) {
+        let delay = (next - Utc::now()).to_std().unwrap_or_default();
+        ctx.request_repaint_after(delay);
+    }
+    let toasts: Vec<Notification> = ui_state
+        .notifications
+        .toasts
+        .iter()
+        .filter_map(|toast| ui_state.notifications.get(&toast.message_id).cloned())
+        .collect();
+    if toasts.is_empty() {
+        return;
+    }
+
+    let colors = theme.current_colors();
+    let mut opened = None;
+    let mut dismissed = None;
+    egui::Area::new(egui::Id::new("notification_toasts"))
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-16.0, -16.0))
+        .order(egui::Order::Foreground)
+        .show(ctx, |ui| {
+            for notification in toasts.iter().rev() {
+                let response = egui::Frame::popup(ui.style())
+                    .fill(colors.background)
+                    .stroke(egui::Stroke::new(1.0, colors.border))
+                    .show(ui, |ui| {
+                        ui.set_width(TOAST_WIDTH);
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                RichText::new(&notification.chat_name)
+                                    .font(theme.fonts.title.clone())
+                                    .color(theme.text_styles.chat_title.color),
+                            );
+                            ui.with_layout(
+                                egui::Layout::right_to_left(egui::Align::Center),
+                                |ui| {
+                                    if ui.small_button("✕").clicked() {
+                                        dismissed = Some(notification.message_id.clone());
+                                    }
+                                },
+                            );
+                        });
+                        render_preview(ui, notification, theme);
+                    })
+                    .response
+                    .interact(egui::Sense::click());
+                if response.clicked() {
+                    opened = Some(notification.message_id.clone());
+                }
+                ui.add_space(8.0);
+            }
+        });
+
+    if let Some(message_id) = dismissed {
+        ui_state.notifications.dismiss_toast(&message_id);
+    } else if let Some(message_id) = opened {
+        ui_state.open_notification(&message_id);
+    }
+}
+
+/// 通知中心，列出最近的 @我 和新消息
+pub fn notification_center_ui(
+    ctx: &egui::Context,
+    ui_state: &mut ResMut<UiState>,
+    theme: &NotificationTheme,
+) {
+    if !ui_state.notifications.show_panel {
+        return;
+    }
+    let mut open = true;
+    let mut opened = None;
+    let now = Local::now();
+    egui::Window::new("通知")
+        .open(&mut open)
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-16.0, 16.0))
+        .default_width(TOAST_WIDTH + 40.0)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new(format!("{} 条未读", ui_state.notifications.unread_count()))
+                        .color(theme.text_styles.chat_time.color),
+                );
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let has_unread = ui_state.notifications.unread_count() > 0;
+                    if ui
+                        .add_enabled(has_unread, egui::Button::new("全部已读"))
+                        .clicked()
+                    {
+                        ui_state.notifications.mark_all_read();
+                    }
+                });
+            });
+            ui.separator();
+            if ui_state.notifications.items.is_empty() {
+                ui.label(RichText::new("暂无通知").color(theme.text_styles.chat_time.color));
+                return;
+            }
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                for notification in &ui_state.notifications.items {
+                    let response = ui
+                        .vertical(|ui| {
+                            ui.horizontal(|ui| {
+                                if !notification.read {
+                                    let (rect, _) = ui.allocate_exact_size(
+                                        egui::vec2(8.0, 8.0),
+                                        egui::Sense::hover(),
+                                    );
+                                    ui.painter().circle_filled(
+                                        rect.center(),
+                                        4.0,
+                                        theme.text_styles.nav_notification.color,
+                                    );
+                                }
+                                ui.label(
+                                    RichText::new(&notification.chat_name)
+                                        .color(theme.text_styles.chat_title.color)
+                                        .strong(),
+                                );
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        ui.label(
+                                            RichText::new(format_list_time(
+                                                &notification.received_at,
+                                                &now,
+                                            ))
+                                            .color(theme.text_styles.chat_time.color),
+                                        );
+                                    },
+                                );
+                            });
+                            render_preview(ui, notification, theme);
+                        })
+                        .response
+                        .interact(egui::Sense::click())
+                        .on_hover_cursor(egui::CursorIcon::PointingHand);
+                    if response.clicked() {
+                        opened = Some(notification.message_id.clone());
+                    }
+                    ui.separator();
+                }
+            });
+        });
+
+    if !open {
+        ui_state.notifications.show_panel = false;
+    }
+    if let Some(message_id) = opened {
+        ui_state.notifications.show_panel = false;
+        ui_state.open_notification(&message_id);
+    }
+}
+
+fn render_preview(ui: &mut egui::Ui, notification: &Notification, theme: &NotificationTheme) {
+    ui.horizontal_wrapped(|ui| {
+        if notification.mentions_me {
+            ui.label(
+                RichText::new("[有人@我]").color(theme.text_styles.nav_notification.color),
+            );
+        }
+        ui.label(
+            RichText::new(format!("{}: {}", notification.sender, notification.preview))
+                .color(theme.text_styles.chat_message.color),
+        );
+    });
+}
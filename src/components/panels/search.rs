@@ -0,0 +1,176 @@
+use bevy::prelude::ResMut;
+use bevy_egui::egui::{
+    self, text::LayoutJob, Color32, FontId, Margin, RichText, ScrollArea, TextFormat, UiBuilder,
+};
+use chrono::Local;
+
+use crate::{
+    format_hover_time,
+    resources::{NotificationTheme, UiState},
+    search::{snippet, SearchHit, SearchKind, SearchSort, Snippet},
+};
+
+/// 全局搜索页：输入框和按类别分组的结果
+pub fn search_ui(
+    ctx: &egui::Context,
+    ui_state: &mut ResMut<UiState>,
+    theme: &NotificationTheme,
+) -> egui::InnerResponse<()> {
+    let colors = theme.current_colors();
+    egui::CentralPanel::default()
+        .frame(egui::Frame {
+            fill: colors.background,
+            inner_margin: Margin::same(16.0),
+            ..Default::default()
+        })
+        .show(ctx, |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut ui_state.search_text)
+                    .desired_width(ui.available_width())
+                    .hint_text("搜索消息、会话、联系人和文件")
+                    .text_color(theme.text_styles.chat_message.color),
+            );
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new("from:名字  in:\"会话\"  has:file/code/image  before:/after:2024-01-31  \"整句\"")
+                        .size(12.0)
+                        .color(theme.text_styles.chat_time.color),
+                );
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    for sort in [SearchSort::Newest, SearchSort::Relevance] {
+                        ui.selectable_value(&mut ui_state.search_sort, sort, sort.label());
+                    }
+                });
+            });
+            ui.add_space(12.0);
+
+            ui_state.refresh_search();
+            if ui_state.search_results.input.is_empty() {
+                return;
+            }
+            if ui_state.search_results.is_empty() {
+                ui.label(
+                    RichText::new("没有找到相关结果").color(theme.text_styles.chat_time.color),
+                );
+                return;
+            }
+
+            let mut opened = None;
+            ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .show(ui, |ui| {
+                    let results = &ui_state.search_results;
+                    for (kind, hits) in &results.groups {
+                        ui.label(
+                            RichText::new(format!("{}（{}）", kind.label(), hits.len()))
+                                .font(theme.fonts.title.clone())
+                                .color(theme.text_styles.chat_title.color)
+                                .strong(),
+                        );
+                        ui.add_space(4.0);
+                        for hit in hits {
+                            let chat_name = ui_state
+                                .chat_data
+                                .chats
+                                .iter()
+                                .find(|chat| chat.id == hit.chat_id)
+                                .map(|chat| chat.name.as_str())
+                                .unwrap_or_default();
+                            if render_hit(ui, hit, chat_name, &results.terms, theme) {
+                                opened = Some(hit.clone());
+                            }
+                        }
+                        ui.add_space(12.0);
+                    }
+                });
+
+            if let Some(hit) = opened {
+                ui_state.open_search_hit(&hit);
+            }
+        })
+}
+
+/// 一条结果：标题、带高亮的摘要，右侧是所在会话和时间。返回是否被点击
+fn render_hit(
+    ui: &mut egui::Ui,
+    hit: &SearchHit,
+    chat_name: &str,
+    terms: &[String],
+    theme: &NotificationTheme,
+) -> bool {
+    let colors = theme.current_colors();
+    let response = ui.add(
+        egui::Button::new("")
+            .frame(false)
+            .fill(Color32::TRANSPARENT)
+            .min_size(egui::vec2(ui.available_width(), 48.0)),
+    );
+    if response.hovered() {
+        ui.painter()
+            .rect_filled(response.rect, theme.style.rounding, colors.hover);
+    }
+
+    ui.allocate_new_ui(UiBuilder::new().max_rect(response.rect.shrink(6.0)), |ui| {
+        ui.horizontal(|ui| {
+            let title = match hit.kind {
+                SearchKind::Chat | SearchKind::Contact => snippet(&hit.title, terms),
+                SearchKind::Message | SearchKind::File => Snippet {
+                    text: hit.title.clone(),
+                    highlights: Vec::new(),
+                },
+            };
+            ui.label(highlighted(
+                &title,
+                theme.fonts.title.clone(),
+                theme.text_styles.chat_title.color,
+                colors.accent,
+            ));
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let mut detail = chat_name.to_string();
+                if let Some(sent_at) = &hit.sent_at {
+                    detail = format!("{}  {}", detail, format_hover_time(sent_at, &Local::now()));
+                }
+                ui.label(
+                    RichText::new(detail)
+                        .font(theme.fonts.timestamp.clone())
+                        .color(theme.text_styles.chat_time.color),
+                );
+            });
+        });
+
+        if matches!(hit.kind, SearchKind::Message | SearchKind::File) {
+            ui.label(highlighted(
+                &snippet(&hit.body, terms),
+                theme.fonts.content.clone(),
+                theme.text_styles.chat_message.color,
+                colors.accent,
+            ));
+        }
+    });
+
+    response.clicked()
+}
+
+/// 把摘要转成带高亮的排版
+pub fn highlighted(snippet: &Snippet, font: FontId, color: Color32, highlight: Color32) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    let normal = TextFormat {
+        font_id: font.clone(),
+        color,
+        ..Default::default()
+    };
+    let marked = TextFormat {
+        font_id: font,
+        color: highlight,
+        ..Default::default()
+    };
+    let mut cursor = 0;
+    for range in &snippet.highlights {
+        job.append(&snippet.text[cursor..range.start], 0.0, normal.clone());
+        job.append(&snippet.text[range.clone()], 0.0, marked.clone());
+        cursor = range.end;
+    }
+    job.append(&snippet.text[cursor..], 0.0, normal);
+    job
+}
@@ -1,10 +1,22 @@
 use bevy::{prelude::*, winit::WinitSettings};
+use backend::{load_history, poll_backend_events, process_outbox, subscribe_backend};
 use bevy_egui::EguiPlugin;
 use components::*;
-use resources::{setup_ui, AppState, NotificationTheme, OccupiedScreenSpace, UiState};
+use resources::{
+    expire_user_status, setup_ui, AppState, NotificationTheme, OccupiedScreenSpace, UiState,
+};
 
+mod backend;
 mod components;
 mod resources;
+mod search;
+mod store;
+
+// 供 benches 使用
+pub use components::{
+    ChatMessage, DeliveryState, MessageContent, MessageTimeline, MessageType, TimelineRow,
+};
+
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
@@ -15,14 +27,25 @@ impl Plugin for UiPlugin {
             .init_resource::<NotificationTheme>()
             .init_resource::<OccupiedScreenSpace>()
             .init_resource::<AppState>()
-            .add_systems(Startup, setup_ui)
+            .add_systems(Startup, (setup_ui, subscribe_backend))
             .add_systems(
                 Update,
                 (
                     splash_start.run_if(resource_equals(AppState::SplashStart)),
                     splash_to_ui.run_if(resource_equals(AppState::UiSetup)),
                     animate_splash.run_if(resource_equals(AppState::SplashAnimate)),
-                    (main_ui_system,).run_if(resource_equals(AppState::Running)),
+                    (
+                        poll_backend_events,
+                        process_outbox,
+                        load_history,
+                        expire_user_status,
+                        main_ui_system,
+                        sync_chat_windows,
+                        chat_window_ui,
+                        run_file_dialogs,
+                    )
+                        .chain()
+                        .run_if(resource_equals(AppState::Running)),
                 ),
             );
     }
@@ -1,7 +1,25 @@
-use crate::{Chat, ChatMessage, ChatType, MessageType};
-use std::{collections::HashMap, default, hash::Hash};
+use crate::{
+	backend::{ChatBackend, MockBackend, Outbox, OutboxEntry, DEFAULT_PAGE_SIZE},
+	search::{
+		ChatSearch, SearchHit, SearchKind, SearchQuery, SearchResults, SearchSort, CHAT_SEARCH_LIMIT,
+		RESULTS_PER_KIND,
+	},
+	store::{MessageStore, StoreError},
+	AttachmentLoader, Chat, ChatAction, ChatFilter, ChatMessage, ChatType, DeliveryState, Lightbox, MessageContent,
+	MessageTimeline, MessageType, NotificationLevel, Presence, ReadReceipt, Segment, TopicIndex,
+	UserStatus, CURRENT_USER,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use std::{
+	collections::{HashMap, HashSet},
+	default,
+	hash::Hash,
+};
 
-use bevy::prelude::{Component, Resource};
+use bevy::{
+	log::warn,
+	prelude::{Component, Resource},
+};
 use bevy_egui::egui::Vec2;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,10 +43,17 @@ pub struct UiState {
 	pub selected_nav_index: usize,
 	pub show_avatar_menu: bool,
 	pub show_status_menu: bool,
+	pub user_status: Option<UserStatus>,
+	pub status_draft: StatusDraft,
+	pub notifications: NotificationCenter,
+	/// 在前台的独立窗口里打开着的聊天，每帧由 `chat_window_ui` 更新
+	pub focused_windows: HashSet<String>,
 	pub show_siderbar: bool,
-	pub selected_siderbar_button: String,
+	pub chat_filter: ChatFilter,
 	pub current_tab: ChatTab,
 	pub search_text: String,
+	pub search_sort: SearchSort,
+	pub search_results: SearchResults,
 
 	// Chat main ui
 	pub current_message_type: MessageType,
@@ -36,50 +61,530 @@ pub struct UiState {
 	pub select_chat_id: String,
 	pub show_emoji_picker: bool,
 	pub show_pin_message: bool,
+	pub timeline: MessageTimeline,
+	pub topics: TopicIndex,
+	/// 从搜索结果跳转过来时高亮的消息
+	pub focused_message: Option<String>,
+	/// 话题群里正在回复的话题
+	pub replying_to: Option<String>,
+	pub chat_search: ChatSearch,
+	/// 发送代码消息时选的语言
+	pub code_language: String,
+	pub lightbox: Option<Lightbox>,
+	/// 输入框上方待发送的图片和文件
+	pub draft_segments: Vec<Segment>,
+	/// 正在后台读取的附件
+	pub attachments: AttachmentLoader,
 
 	// Chat content
-	pub messages: Vec<ChatMessage>,
-	pub chats: Vec<Chat>,
-	pub unread_counts: HashMap<String, i32>,
+	pub backend: Box<dyn ChatBackend>,
+	pub store: MessageStore,
+	pub outbox: Outbox,
+	pub chat_data: ChatData,
 }
 
 impl Default for UiState {
 	fn default() -> Self {
-		let chat_data = ChatData::create_default_chat_rooms();
-		let mut chats = Vec::new();
-		let mut messages = Vec::new();
-		let mut unread_counts = HashMap::new();
+		Self::with_store(Box::new(MockBackend::new()), open_store())
+	}
+}
 
-		for (_, room_data) in chat_data.iter() {
-			chats.push(room_data.chat.clone());
-			messages.extend(room_data.messages.clone());
-			unread_counts.insert(room_data.chat.id.clone(), room_data.unread_count);
+impl UiState {
+	/// 用给定的后端和本地库初始化，库是空的时候先用后端数据填充
+	pub fn with_store(backend: Box<dyn ChatBackend>, store: MessageStore) -> Self {
+		if store.is_empty().unwrap_or(true) {
+			if let Err(err) = seed_store(&store, backend.as_ref()) {
+				warn!("failed to seed message store: {}", err);
+			}
 		}
 
-		Self {
+		let outbox = Outbox::from_entries(store.load_outbox().unwrap_or_else(|err| {
+			warn!("failed to load outbox: {}", err);
+			Vec::new()
+		}));
+		let chat_data = ChatData {
+			chats: store.load_chats().unwrap_or_else(|err| {
+				warn!("failed to load chats: {}", err);
+				backend.list_chats()
+			}),
+			unread_counts: store.load_unread_counts().unwrap_or_default(),
+			read_cursors: store.load_read_cursors().unwrap_or_default(),
+			latest_messages: store.load_latest_messages().unwrap_or_else(|err| {
+				warn!("failed to load latest messages: {}", err);
+				HashMap::new()
+			}),
+			unread_mentions: store.load_unread_mentions(CURRENT_USER).unwrap_or_default(),
+			..Default::default()
+		};
+
+		let mut state = Self {
 			nav_width: 50.0,
 			selected_nav_index: 1,
 			show_avatar_menu: false,
 			show_status_menu: false,
+			user_status: store
+				.load_user_status()
+				.unwrap_or_else(|err| {
+					warn!("failed to load user status: {}", err);
+					None
+				})
+				.filter(|status| !status.is_expired(Utc::now())),
+			status_draft: StatusDraft::default(),
+			notifications: NotificationCenter::default(),
+			focused_windows: HashSet::new(),
 			show_siderbar: false,
-			select_chat_id: "1".to_string(),
+			select_chat_id: String::new(),
 			current_tab: ChatTab::Message,
-			search_text: "Search Contact/Documents".to_string(),
-			selected_siderbar_button: String::new(),
+			search_text: String::new(),
+			search_sort: SearchSort::default(),
+			search_results: SearchResults::default(),
+			chat_filter: ChatFilter::All,
 			current_message_type: MessageType::Text,
 			input_text: String::new(),
 			show_emoji_picker: false,
 			show_pin_message: false,
-			messages,
-			chats,
-			unread_counts,
+			timeline: MessageTimeline::default(),
+			topics: TopicIndex::default(),
+			focused_message: None,
+			replying_to: None,
+			chat_search: ChatSearch::default(),
+			code_language: "rs".to_string(),
+			lightbox: None,
+			draft_segments: Vec::new(),
+			attachments: AttachmentLoader::default(),
+			backend,
+			store,
+			outbox,
+			chat_data,
+		};
+		state.select_chat("1");
+		state
+	}
+}
+
+/// 聊天视图自己的状态。主窗口的放在 `UiState` 里，每个独立窗口各有一份，
+/// 渲染独立窗口前用 `UiState::swap_view` 换进来，渲染完再换回去
+pub struct ChatViewState {
+	pub chat_id: String,
+	pub current_tab: ChatTab,
+	pub current_message_type: MessageType,
+	pub input_text: String,
+	pub show_emoji_picker: bool,
+	pub timeline: MessageTimeline,
+	pub topics: TopicIndex,
+	pub focused_message: Option<String>,
+	pub replying_to: Option<String>,
+	pub chat_search: ChatSearch,
+	pub code_language: String,
+	pub lightbox: Option<Lightbox>,
+	pub draft_segments: Vec<Segment>,
+}
+
+impl ChatViewState {
+	pub fn new(chat_id: &str) -> Self {
+		Self {
+			chat_id: chat_id.to_string(),
+			current_tab: ChatTab::Message,
+			current_message_type: MessageType::Text,
+			input_text: String::new(),
+			show_emoji_picker: false,
+			timeline: MessageTimeline::default(),
+			topics: TopicIndex::default(),
+			focused_message: None,
+			replying_to: None,
+			chat_search: ChatSearch::default(),
+			code_language: "rs".to_string(),
+			lightbox: None,
+			draft_segments: Vec::new(),
 		}
 	}
 }
 
 impl UiState {
+	pub fn swap_view(&mut self, view: &mut ChatViewState) {
+		std::mem::swap(&mut self.select_chat_id, &mut view.chat_id);
+		std::mem::swap(&mut self.current_tab, &mut view.current_tab);
+		std::mem::swap(&mut self.current_message_type, &mut view.current_message_type);
+		std::mem::swap(&mut self.input_text, &mut view.input_text);
+		std::mem::swap(&mut self.show_emoji_picker, &mut view.show_emoji_picker);
+		std::mem::swap(&mut self.timeline, &mut view.timeline);
+		std::mem::swap(&mut self.topics, &mut view.topics);
+		std::mem::swap(&mut self.focused_message, &mut view.focused_message);
+		std::mem::swap(&mut self.replying_to, &mut view.replying_to);
+		std::mem::swap(&mut self.chat_search, &mut view.chat_search);
+		std::mem::swap(&mut self.code_language, &mut view.code_language);
+		std::mem::swap(&mut self.lightbox, &mut view.lightbox);
+		std::mem::swap(&mut self.draft_segments, &mut view.draft_segments);
+	}
+
+	/// 切换到指定聊天并标记为已读，之前加载过的消息会保留
+	pub fn select_chat(&mut self, chat_id: &str) {
+		self.select_chat_id = chat_id.to_string();
+		self.focused_message = None;
+		self.replying_to = None;
+		self.ensure_messages_loaded(chat_id);
+		self.mark_as_read(chat_id);
+		if self.chat_data.chat(chat_id).is_some_and(|chat| chat.marked_unread) {
+			self.update_chat(chat_id, |chat| chat.marked_unread = false);
+		}
+	}
+
+	/// 聊天列表右键菜单的操作
+	pub fn apply_chat_action(&mut self, chat_id: &str, action: ChatAction) {
+		match action {
+			ChatAction::TogglePin => self.update_chat(chat_id, |chat| chat.pin = !chat.pin),
+			ChatAction::ToggleUnread => {
+				let has_unread = self.chat_data.unread_counts.get(chat_id).is_some_and(|n| *n > 0);
+				let marked = self.chat_data.chat(chat_id).is_some_and(|chat| chat.marked_unread);
+				if has_unread || marked {
+					self.mark_as_read(chat_id);
+					self.update_chat(chat_id, |chat| chat.marked_unread = false);
+				} else {
+					self.update_chat(chat_id, |chat| chat.marked_unread = true);
+				}
+			}
+			ChatAction::ToggleFlag => {
+				self.update_chat(chat_id, |chat| chat.flagged = !chat.flagged)
+			}
+			ChatAction::ToggleTag(tag) => {
+				let tag = tag.trim().to_string();
+				if tag.is_empty() {
+					return;
+				}
+				self.update_chat(chat_id, |chat| {
+					if let Some(index) = chat.tags.iter().position(|t| *t == tag) {
+						chat.tags.remove(index);
+					} else {
+						chat.tags.push(tag);
+					}
+				});
+			}
+			ChatAction::SetNotification(level) => {
+				self.update_chat(chat_id, |chat| chat.notification = level)
+			}
+			ChatAction::ToggleDone => self.update_chat(chat_id, |chat| chat.done = !chat.done),
+			ChatAction::ToggleNavPin => {
+				self.update_chat(chat_id, |chat| chat.nav_pinned = !chat.nav_pinned)
+			}
+			ChatAction::ToggleWindow => {
+				self.update_chat(chat_id, |chat| chat.detached = !chat.detached)
+			}
+		}
+	}
+
+	/// 修改聊天的本地设置并保存
+	fn update_chat(&mut self, chat_id: &str, change: impl FnOnce(&mut Chat)) {
+		let Some(chat) = self.chat_data.chats.iter_mut().find(|chat| chat.id == chat_id) else {
+			return;
+		};
+		change(chat);
+		if let Err(err) = self.store.save_chat(chat) {
+			warn!("failed to save chat: {}", err);
+		}
+	}
+
+	pub fn current_messages(&self) -> &[ChatMessage] {
+		self.chat_data.get_message_for_chat(&self.select_chat_id)
+	}
+
+	pub fn ensure_messages_loaded(&mut self, chat_id: &str) {
+		if self.chat_data.is_loaded(chat_id) {
+			return;
+		}
+		let messages = self
+			.store
+			.load_messages(chat_id, None, DEFAULT_PAGE_SIZE)
+			.map(|page| page.messages)
+			.unwrap_or_else(|err| {
+				warn!("failed to load messages: {}", err);
+				Vec::new()
+			});
+		self.chat_data.set_messages(chat_id, messages);
+	}
+
+	/// 滚动到顶部时调用，实际加载在下一帧开始前由 `load_history` 完成，这一帧先显示加载中
+	pub fn request_older_messages(&mut self, chat_id: &str) {
+		if self.chat_data.history_state(chat_id) == HistoryState::Idle {
+			self.chat_data.set_history_state(chat_id, HistoryState::Loading);
+		}
+	}
+
+	pub fn load_requested_history(&mut self) {
+		let loading: Vec<String> = self
+			.chat_data
+			.history
+			.iter()
+			.filter(|(_, state)| **state == HistoryState::Loading)
+			.map(|(chat_id, _)| chat_id.clone())
+			.collect();
+		for chat_id in loading {
+			let state = self.load_older_messages(&chat_id).unwrap_or_else(|err| {
+				warn!("failed to load older messages: {}", err);
+				HistoryState::Idle
+			});
+			self.chat_data.set_history_state(&chat_id, state);
+		}
+	}
+
+	/// 先从本地数据库取更早的一页，本地没有了再向后端要
+	fn load_older_messages(&mut self, chat_id: &str) -> Result<HistoryState, StoreError> {
+		let before = self
+			.chat_data
+			.get_message_for_chat(chat_id)
+			.first()
+			.map(|msg| msg.id.clone());
+		let page = self
+			.store
+			.load_messages(chat_id, before.as_deref(), DEFAULT_PAGE_SIZE)?;
+		if self.chat_data.prepend_messages(chat_id, page.messages) > 0 {
+			return Ok(HistoryState::Idle);
+		}
+
+		let page = self
+			.backend
+			.fetch_messages(chat_id, before.as_deref(), DEFAULT_PAGE_SIZE);
+		for message in &page.messages {
+			self.store.save_message(message)?;
+		}
+		let added = self.chat_data.prepend_messages(chat_id, page.messages);
+		// 后端也没有更早的消息，或者给回来的都已经有了，就认为到头了
+		if added == 0 || !page.has_more {
+			Ok(HistoryState::Exhausted)
+		} else {
+			Ok(HistoryState::Idle)
+		}
+	}
+
+	pub fn mark_as_read(&mut self, chat_id: &str) {
+		self.backend.mark_read(chat_id);
+		let read_at = self.chat_data.mark_as_read(chat_id);
+		self.notifications.mark_chat_read(chat_id);
+		if let Err(err) = self.store.set_unread_count(chat_id, 0) {
+			warn!("failed to save unread count: {}", err);
+		}
+		if let Err(err) = self.store.save_read_cursor(chat_id, CURRENT_USER, read_at) {
+			warn!("failed to save read cursor: {}", err);
+		}
+	}
+
+	/// 其他成员读到了 `read_at` 之前的消息
+	pub fn apply_read_receipt(&mut self, chat_id: &str, user: &str, read_at: DateTime<Utc>) {
+		if !self.chat_data.update_read_cursor(chat_id, user, read_at) {
+			return;
+		}
+		if let Err(err) = self.store.save_read_cursor(chat_id, user, read_at) {
+			warn!("failed to save read cursor: {}", err);
+		}
+	}
+
+	/// 主窗口选中的聊天和前台独立窗口里的聊天，新消息直接算已读
+	pub fn is_reading(&self, chat_id: &str) -> bool {
+		chat_id == self.select_chat_id || self.focused_windows.contains(chat_id)
+	}
+
+	/// 保存一条新消息，没有在看这个聊天时累加未读数
+	pub fn append_message(&mut self, message: ChatMessage) {
+		let chat_id = message.chat_id.clone();
+		self.ensure_messages_loaded(&chat_id);
+		if let Err(err) = self.store.save_message(&message) {
+			warn!("failed to save message: {}", err);
+		}
+		self.notify(&message);
+		self.chat_data.add_message(&chat_id, message);
+		if self.is_reading(&chat_id) {
+			self.mark_as_read(&chat_id);
+		} else if let Some(count) = self.chat_data.unread_counts.get(&chat_id) {
+			if let Err(err) = self.store.set_unread_count(&chat_id, *count) {
+				warn!("failed to save unread count: {}", err);
+			}
+		}
+	}
+
+	/// 本地回显一条自己发的消息，并放进发件箱等待投递
+	pub fn queue_message(&mut self, message: ChatMessage) {
+		let entry = self.outbox.enqueue(message, Utc::now());
+		self.append_message(entry.message.clone());
+		if let Err(err) = self.store.save_outbox_entry(&entry) {
+			warn!("failed to save outbox entry: {}", err);
+		}
+	}
+
+	/// 服务端确认收到，用服务端的版本替换本地回显
+	pub fn complete_delivery(&mut self, sent: ChatMessage) {
+		self.outbox.complete(&sent.client_key);
+		if let Err(err) = self.store.remove_outbox_entry(&sent.client_key) {
+			warn!("failed to remove outbox entry: {}", err);
+		}
+		self.append_message(ChatMessage {
+			delivery: DeliveryState::Sent,
+			..sent
+		});
+	}
+
+	pub fn fail_delivery(&mut self, client_key: &str) {
+		if let Some(entry) = self.outbox.fail(client_key, Utc::now()) {
+			self.save_outbox_update(entry);
+		}
+	}
+
+	pub fn retry_message(&mut self, client_key: &str) {
+		if let Some(entry) = self.outbox.retry(client_key, Utc::now()) {
+			self.save_outbox_update(entry);
+		}
+	}
+
+	fn save_outbox_update(&mut self, entry: OutboxEntry) {
+		if let Err(err) = self.store.save_outbox_entry(&entry) {
+			warn!("failed to save outbox entry: {}", err);
+		}
+		self.update_message(entry.message);
+	}
+
+	/// 更新一条已有消息（例如投递状态），不影响未读数
+	fn update_message(&mut self, message: ChatMessage) {
+		if let Err(err) = self.store.save_message(&message) {
+			warn!("failed to save message: {}", err);
+		}
+		if self.chat_data.is_loaded(&message.chat_id) {
+			let chat_id = message.chat_id.clone();
+			self.chat_data.add_message(&chat_id, message);
+		}
+	}
+
+	/// 搜索语句或排序变化时重新查询，结果按类别分组
+	pub fn refresh_search(&mut self) {
+		let input = self.search_text.trim();
+		if input == self.search_results.input && self.search_sort == self.search_results.sort {
+			return;
+		}
+		let query = SearchQuery::parse(input);
+		let mut results = SearchResults {
+			input: input.to_string(),
+			sort: self.search_sort,
+			terms: query.highlight_terms(),
+			groups: Vec::new(),
+		};
+		for kind in SearchKind::ALL {
+			// 带过滤条件时只搜消息和文件
+			if query.has_filters() && !matches!(kind, SearchKind::Message | SearchKind::File) {
+				continue;
+			}
+			match self
+				.store
+				.search(&query, kind, self.search_sort, RESULTS_PER_KIND)
+			{
+				Ok(hits) if !hits.is_empty() => results.groups.push((kind, hits)),
+				Ok(_) => {}
+				Err(err) => warn!("failed to search {}: {}", kind.label(), err),
+			}
+		}
+		self.search_results = results;
+	}
+
+	/// 打开搜索结果所在的聊天，消息和文件会滚动到对应位置并高亮
+	pub fn open_search_hit(&mut self, hit: &SearchHit) {
+		self.selected_nav_index = 1;
+		self.select_chat(&hit.chat_id);
+		if matches!(hit.kind, SearchKind::Message | SearchKind::File) {
+			self.reveal_message(&hit.chat_id, &hit.ref_id);
+		}
+	}
+
+	pub fn toggle_chat_search(&mut self) {
+		self.chat_search = ChatSearch {
+			open: !self.chat_search.open,
+			..Default::default()
+		};
+		self.focused_message = None;
+	}
+
+	/// 聊天内搜索的输入或当前聊天变化时重新查询，并跳到最新的一条
+	pub fn refresh_chat_search(&mut self) {
+		let input = self.chat_search.text.trim().to_string();
+		let key = (self.select_chat_id.clone(), input.clone());
+		if self.chat_search.searched.as_ref() == Some(&key) {
+			return;
+		}
+		self.chat_search.searched = Some(key);
+		self.chat_search.matches.clear();
+		self.chat_search.current = None;
+		self.focused_message = None;
+
+		let mut query = SearchQuery::parse(&input);
+		self.chat_search.terms = query.highlight_terms();
+		if query.is_empty() {
+			return;
+		}
+		query.chat_id = Some(self.select_chat_id.clone());
+
+		let mut hits = Vec::new();
+		for kind in [SearchKind::Message, SearchKind::File] {
+			match self
+				.store
+				.search(&query, kind, SearchSort::Newest, CHAT_SEARCH_LIMIT)
+			{
+				Ok(found) => hits.extend(found),
+				Err(err) => warn!("failed to search chat: {}", err),
+			}
+		}
+		hits.sort_by_key(|hit| hit.sent_at);
+		self.chat_search.matches = hits.into_iter().map(|hit| hit.ref_id).collect();
+		if let Some(last) = self.chat_search.matches.len().checked_sub(1) {
+			self.chat_search.current = Some(last);
+			let chat_id = self.select_chat_id.clone();
+			let id = self.chat_search.matches[last].clone();
+			self.reveal_message(&chat_id, &id);
+		}
+	}
+
+	/// 跳到更早或更新的一条命中，需要时加载更早的历史
+	pub fn step_chat_search(&mut self, older: bool) {
+		if let Some(id) = self.chat_search.step(older) {
+			let chat_id = self.select_chat_id.clone();
+			self.reveal_message(&chat_id, &id);
+		}
+	}
+
+	/// 向前加载历史直到找到这条消息，然后滚动过去
+	pub fn reveal_message(&mut self, chat_id: &str, message_id: &str) {
+		let contains = |state: &Self| {
+			state
+				.chat_data
+				.get_message_for_chat(chat_id)
+				.iter()
+				.any(|msg| msg.id == message_id)
+		};
+		while !contains(self) {
+			match self.load_older_messages(chat_id) {
+				Ok(HistoryState::Idle) => {}
+				Ok(state) => {
+					self.chat_data.set_history_state(chat_id, state);
+					break;
+				}
+				Err(err) => {
+					warn!("failed to load older messages: {}", err);
+					break;
+				}
+			}
+		}
+		if contains(self) {
+			self.timeline.scroll_to(message_id);
+			self.focused_message = Some(message_id.to_string());
+		}
+	}
+
+	pub fn current_chat_type(&self) -> ChatType {
+		self.chat_data
+			.chats
+			.iter()
+			.find(|c| c.id == self.select_chat_id)
+			.map(|c| c.chat_type.clone())
+			.unwrap_or_default()
+	}
+
 	pub fn current_chat_name(&self) -> String {
-		self.chats
+		self.chat_data
+			.chats
 			.iter()
 			.find(|c| c.id == self.select_chat_id)
 			.map(|c| c.name.clone())
@@ -87,6 +592,32 @@ impl UiState {
 	}
 }
 
+fn open_store() -> MessageStore {
+	MessageStore::open_default()
+		.or_else(|err| {
+			warn!("failed to open message store, falling back to memory: {}", err);
+			MessageStore::open_in_memory()
+		})
+		.expect("failed to open in-memory message store")
+}
+
+/// 首次启动时用后端数据初始化本地库
+fn seed_store(store: &MessageStore, backend: &dyn ChatBackend) -> Result<(), StoreError> {
+	for chat in backend.list_chats() {
+		store.save_chat(&chat)?;
+		for message in backend
+			.fetch_messages(&chat.id, None, DEFAULT_PAGE_SIZE)
+			.messages
+		{
+			store.save_message(&message)?;
+		}
+	}
+	for (chat_id, count) in backend.unread_counts() {
+		store.set_unread_count(&chat_id, count)?;
+	}
+	Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum NavPage {
 	Message,
@@ -167,8 +698,31 @@ impl ChatRoomData {
 
 pub struct ChatData {
 	pub chats: Vec<Chat>,
-	pub messages: Vec<ChatMessage>,
+	/// 按 chat_id 索引的已加载消息，每个聊天内按时间从旧到新排列
+	pub messages: HashMap<String, Vec<ChatMessage>>,
 	pub unread_counts: HashMap<String, i32>,
+	/// chat_id -> 成员 -> 已读到的时间，自己的已读位置决定未读数
+	pub read_cursors: HashMap<String, HashMap<String, DateTime<Utc>>>,
+	/// 每个聊天更早消息的加载状态
+	pub history: HashMap<String, HistoryState>,
+	/// 每个聊天最新的一条消息，没加载过消息的聊天也有，聊天列表用
+	pub latest_messages: HashMap<String, ChatMessage>,
+	/// 每个聊天未读消息里 @ 了自己的条数
+	pub unread_mentions: HashMap<String, i32>,
+	/// 用户名 -> 在线状态，只在内存里保存
+	pub presence: HashMap<String, Presence>,
+	/// 每个聊天消息列表的版本号，增加、替换消息时加一，界面用它判断缓存是否过期
+	revisions: HashMap<String, u64>,
+}
+
+/// 向上翻页加载历史消息的状态
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum HistoryState {
+	#[default]
+	Idle,
+	Loading,
+	/// 已经到会话开头，没有更早的消息了
+	Exhausted,
 }
 
 impl ChatData {
@@ -184,7 +738,8 @@ impl ChatData {
 			self.chats.push(room_data.chat.clone());
 			self.unread_counts
 				.insert(room_data.chat.id.clone(), room_data.unread_count);
-			self.messages.extend(room_data.messages.clone());
+			self.messages
+				.insert(room_data.chat.id.clone(), room_data.messages.clone());
 		}
 	}
 
@@ -201,17 +756,27 @@ impl ChatData {
 					last_message: Some("Welcome to the group".to_string()),
 					chat_type: ChatType::Group,
 					pin: true,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: false,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
 				},
 				vec![
 					ChatMessage {
 						id: "1-1".to_string(),
+						client_key: String::new(),
 						chat_id: "1".to_string(),
 						sender: "Ray".to_string(),
 						avatar: "R".to_string(),
-						content: "Wellcome to the Lark Chat Group".to_string(),
-						timestamp: "2021-09-01 12:00:00".to_string(),
+						content: MessageContent::text("Wellcome to the Lark Chat Group"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 4, 0, 0).unwrap(),
 						message_type: MessageType::Text,
-
+						delivery: DeliveryState::Sent,
+						reply_to: None,
 					}
 				],
 				3
@@ -229,44 +794,422 @@ impl ChatData {
 					last_message: Some("Welcome to the group".to_string()),
 					chat_type: ChatType::Group,
 					pin: true,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: false,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
 				},
 				vec![
 					ChatMessage {
 						id: "2-1".to_string(),
+						client_key: String::new(),
 						chat_id: "2".to_string(),
 						sender: "Ray".to_string(),
 						avatar: "R".to_string(),
-						content: "Wellcome to the Lark Chat Group".to_string(),
-						timestamp: "2021-09-01 12:00:00".to_string(),
+						content: MessageContent::text("Wellcome to the Lark Chat Group"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 4, 0, 0).unwrap(),
 						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: None,
 					}
 				],
 				3
 			)
 		);
 
+		rooms.insert(
+			"3".to_string(),
+			ChatRoomData::new(
+				Chat {
+					id: "3".to_string(),
+					name: "Alice".to_string(),
+					avatar: "A".to_string(),
+					member_count: 2,
+					last_message: Some("Say hi to Alice".to_string()),
+					chat_type: ChatType::Direct,
+					pin: false,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: false,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
+				},
+				vec![
+					ChatMessage {
+						id: "3-1".to_string(),
+						client_key: String::new(),
+						chat_id: "3".to_string(),
+						sender: "Alice".to_string(),
+						avatar: "A".to_string(),
+						content: MessageContent::text("下午有空对一下需求吗？"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 5, 0, 0).unwrap(),
+						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: None,
+					},
+				],
+				1
+			)
+		);
+
+		rooms.insert(
+			"4".to_string(),
+			ChatRoomData::new(
+				Chat {
+					id: "4".to_string(),
+					name: "构建机器人".to_string(),
+					avatar: "B".to_string(),
+					member_count: 2,
+					last_message: Some("构建和发布通知".to_string()),
+					chat_type: ChatType::Bot,
+					pin: false,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: false,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
+				},
+				vec![
+					ChatMessage {
+						id: "4-1".to_string(),
+						client_key: String::new(),
+						chat_id: "4".to_string(),
+						sender: "构建机器人".to_string(),
+						avatar: "B".to_string(),
+						content: MessageContent::text("main 分支构建成功 ✅"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 6, 0, 0).unwrap(),
+						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: None,
+					},
+				],
+				1
+			)
+		);
+
+		rooms.insert(
+			"5".to_string(),
+			ChatRoomData::new(
+				Chat {
+					id: "5".to_string(),
+					name: "产品讨论".to_string(),
+					avatar: "P".to_string(),
+					member_count: 50,
+					last_message: Some("欢迎在这里发起话题".to_string()),
+					chat_type: ChatType::TopicGroup,
+					pin: false,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: false,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
+				},
+				vec![
+					ChatMessage {
+						id: "5-1".to_string(),
+						client_key: String::new(),
+						chat_id: "5".to_string(),
+						sender: "Bob".to_string(),
+						avatar: "B".to_string(),
+						content: MessageContent::text("新版聊天列表的排序规则大家怎么看？"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 7, 0, 0).unwrap(),
+						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: None,
+					},
+					ChatMessage {
+						id: "5-2".to_string(),
+						client_key: String::new(),
+						chat_id: "5".to_string(),
+						sender: "Carol".to_string(),
+						avatar: "C".to_string(),
+						content: MessageContent::text("置顶的放前面，其余按最后活跃时间"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 8, 0, 0).unwrap(),
+						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: Some("5-1".to_string()),
+					},
+				],
+				2
+			)
+		);
+
+		rooms.insert(
+			"6".to_string(),
+			ChatRoomData::new(
+				Chat {
+					id: "6".to_string(),
+					name: "全员公告".to_string(),
+					avatar: "公".to_string(),
+					member_count: 500,
+					last_message: Some("公司重要通知".to_string()),
+					chat_type: ChatType::Announcement,
+					pin: false,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: false,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
+				},
+				vec![
+					ChatMessage {
+						id: "6-1".to_string(),
+						client_key: String::new(),
+						chat_id: "6".to_string(),
+						sender: "Ray".to_string(),
+						avatar: "R".to_string(),
+						content: MessageContent::text("国庆假期安排已发布，请查收邮件"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 9, 0, 0).unwrap(),
+						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: None,
+					},
+				],
+				1
+			)
+		);
+
+		rooms.insert(
+			"7".to_string(),
+			ChatRoomData::new(
+				Chat {
+					id: "7".to_string(),
+					name: "云文档助手".to_string(),
+					avatar: "D".to_string(),
+					member_count: 1,
+					last_message: Some("Ray 评论了《产品需求文档》".to_string()),
+					chat_type: ChatType::Bot,
+					pin: false,
+					flagged: false,
+					done: false,
+					tags: Vec::new(),
+					cloud_doc: true,
+					notification: NotificationLevel::All,
+					nav_pinned: false,
+					marked_unread: false,
+					detached: false,
+				},
+				vec![
+					ChatMessage {
+						id: "7-1".to_string(),
+						client_key: String::new(),
+						chat_id: "7".to_string(),
+						sender: "Ray".to_string(),
+						avatar: "R".to_string(),
+						content: MessageContent::text("Ray 评论了《产品需求文档》：这一节需要补充验收标准"),
+						timestamp: Utc.with_ymd_and_hms(2021, 9, 1, 5, 0, 0).unwrap(),
+						message_type: MessageType::Text,
+						delivery: DeliveryState::Sent,
+						reply_to: None,
+					}
+				],
+				1
+			)
+		);
+
+
 		rooms
 	}
 
-	pub fn get_message_for_chat(&self, chat_id: &str) -> Vec<ChatMessage> {
+	pub fn get_message_for_chat(&self, chat_id: &str) -> &[ChatMessage] {
 		self.messages
+			.get(chat_id)
+			.map(|messages| messages.as_slice())
+			.unwrap_or_default()
+	}
+
+	pub fn is_loaded(&self, chat_id: &str) -> bool {
+		self.messages.contains_key(chat_id)
+	}
+
+	pub fn set_messages(&mut self, chat_id: &str, messages: Vec<ChatMessage>) {
+		self.messages.insert(chat_id.to_string(), messages);
+		self.touch(chat_id);
+	}
+
+	pub fn revision(&self, chat_id: &str) -> u64 {
+		self.revisions.get(chat_id).copied().unwrap_or(0)
+	}
+
+	fn touch(&mut self, chat_id: &str) {
+		*self.revisions.entry(chat_id.to_string()).or_insert(0) += 1;
+	}
+
+	/// 把更早的一页消息插到最前面，跳过已经有的，返回实际插入的条数
+	pub fn prepend_messages(&mut self, chat_id: &str, older: Vec<ChatMessage>) -> usize {
+		let messages = self.messages.entry(chat_id.to_string()).or_default();
+		let first = messages.first().map(|msg| msg.timestamp);
+		let existing: HashSet<&str> = messages.iter().map(|msg| msg.id.as_str()).collect();
+		let mut older: Vec<ChatMessage> = older
+			.into_iter()
+			.filter(|msg| !existing.contains(msg.id.as_str()))
+			.filter(|msg| first.is_none_or(|first| msg.timestamp <= first))
+			.collect();
+		let count = older.len();
+		older.append(messages);
+		*messages = older;
+		if count > 0 {
+			self.touch(chat_id);
+		}
+		count
+	}
+
+	pub fn history_state(&self, chat_id: &str) -> HistoryState {
+		self.history.get(chat_id).copied().unwrap_or_default()
+	}
+
+	pub fn set_history_state(&mut self, chat_id: &str, state: HistoryState) {
+		self.history.insert(chat_id.to_string(), state);
+	}
+
+	pub fn chat(&self, chat_id: &str) -> Option<&Chat> {
+		self.chats.iter().find(|chat| chat.id == chat_id)
+	}
+
+	/// 需要提醒的未读数，免打扰的聊天是 0
+	pub fn notifying_unread(&self, chat_id: &str) -> i32 {
+		let Some(chat) = self.chat(chat_id) else {
+			return 0;
+		};
+		let unread = self.unread_counts.get(chat_id).copied().unwrap_or(0);
+		let mentions = self.unread_mentions.get(chat_id).copied().unwrap_or(0);
+		match chat.notification {
+			NotificationLevel::All => unread,
+			// 只提醒 @我 的聊天只数 @我 的消息
+			NotificationLevel::Mentions => mentions.min(unread),
+			NotificationLevel::None => 0,
+		}
+	}
+
+	pub fn notifying_unread_total(&self) -> i32 {
+		self.chats
 			.iter()
-			.filter(|msg| msg.chat_id == chat_id)
-			.cloned()
-			.collect()
+			.map(|chat| self.notifying_unread(&chat.id))
+			.sum()
+	}
+
+	pub fn presence_of(&self, user: &str) -> Presence {
+		self.presence.get(user).copied().unwrap_or_default()
+	}
+
+	pub fn latest_message(&self, chat_id: &str) -> Option<&ChatMessage> {
+		let loaded = self.get_message_for_chat(chat_id).last();
+		let cached = self.latest_messages.get(chat_id);
+		match (loaded, cached) {
+			(Some(loaded), Some(cached)) if cached.timestamp > loaded.timestamp => Some(cached),
+			(Some(loaded), _) => Some(loaded),
+			(None, cached) => cached,
+		}
 	}
 
 	pub fn add_message(&mut self, chat_id: &str, message: ChatMessage) {
-		self.messages.push(message);
-		if let Some(count) = self.unread_counts.get_mut(chat_id) {
-			*count += 1;
+		let is_newest = self
+			.latest_messages
+			.get(chat_id)
+			.is_none_or(|latest| {
+				latest.is_same_send(&message) || latest.timestamp <= message.timestamp
+			});
+		if is_newest {
+			self.latest_messages
+				.insert(chat_id.to_string(), message.clone());
+		}
+		let is_unread = !message.is_own()
+			&& self
+				.read_cursor(chat_id, CURRENT_USER)
+				.is_none_or(|read_at| message.timestamp > read_at);
+		self.touch(chat_id);
+		let mentions_me = is_unread && message.mentions_me();
+		let messages = self.messages.entry(chat_id.to_string()).or_default();
+		// 本地回显被服务端确认后替换成服务端的版本，不重复计数
+		if let Some(index) = messages.iter().position(|msg| msg.is_same_send(&message)) {
+			messages.remove(index);
+			let index = messages.partition_point(|msg| msg.timestamp <= message.timestamp);
+			messages.insert(index, message);
+			return;
+		}
+		// 按时间插入，导入的旧消息也能排到正确位置
+		let index = messages.partition_point(|msg| msg.timestamp <= message.timestamp);
+		messages.insert(index, message);
+		if is_unread {
+			*self.unread_counts.entry(chat_id.to_string()).or_insert(0) += 1;
+		}
+		if mentions_me {
+			*self.unread_mentions.entry(chat_id.to_string()).or_insert(0) += 1;
 		}
 	}
 
-	pub fn mark_as_read(&mut self, chat_id: &str) {
+	/// 把自己的已读位置移到最新一条消息，返回新的已读时间
+	pub fn mark_as_read(&mut self, chat_id: &str) -> DateTime<Utc> {
 		if let Some(count) = self.unread_counts.get_mut(chat_id) {
 			*count = 0;
 		}
+		self.unread_mentions.remove(chat_id);
+		let latest = self
+			.get_message_for_chat(chat_id)
+			.last()
+			.map(|msg| msg.timestamp)
+			.unwrap_or_default();
+		let read_at = latest.max(Utc::now());
+		self.update_read_cursor(chat_id, CURRENT_USER, read_at);
+		read_at
+	}
+
+	pub fn read_cursor(&self, chat_id: &str, user: &str) -> Option<DateTime<Utc>> {
+		self.read_cursors.get(chat_id)?.get(user).copied()
+	}
+
+	/// 已读位置只会前进，返回是否有变化
+	pub fn update_read_cursor(&mut self, chat_id: &str, user: &str, read_at: DateTime<Utc>) -> bool {
+		let cursor = self
+			.read_cursors
+			.entry(chat_id.to_string())
+			.or_default()
+			.entry(user.to_string())
+			.or_insert(DateTime::<Utc>::MIN_UTC);
+		if read_at <= *cursor {
+			return false;
+		}
+		*cursor = read_at;
+		true
+	}
+
+	pub fn read_receipt(&self, chat_id: &str, message: &ChatMessage) -> ReadReceipt {
+		let mut read_by: Vec<String> = self
+			.read_cursors
+			.get(chat_id)
+			.into_iter()
+			.flatten()
+			.filter(|(user, read_at)| **user != message.sender && **read_at >= message.timestamp)
+			.map(|(user, _)| user.clone())
+			.collect();
+		read_by.sort();
+		let member_count = self
+			.chats
+			.iter()
+			.find(|chat| chat.id == chat_id)
+			.map_or(0, |chat| chat.member_count);
+		// 成员数里包含发送者自己
+		let unread_count = (member_count - 1 - read_by.len() as i32).max(0);
+		ReadReceipt {
+			read_by,
+			unread_count,
+		}
 	}
 }
 
@@ -274,14 +1217,185 @@ impl Default for ChatData {
 	fn default() -> Self {
 		Self {
 			chats: vec![],
-			messages: vec![],
+			messages: HashMap::new(),
 			unread_counts: HashMap::new(),
+			read_cursors: HashMap::new(),
+			history: HashMap::new(),
+			latest_messages: HashMap::new(),
+			unread_mentions: HashMap::new(),
+			presence: HashMap::new(),
+			revisions: HashMap::new(),
 		}
 	}
 }
 
+mod notification;
 mod setup;
+mod status;
 mod theme;
 
+pub use notification::*;
 pub use setup::*;
+pub use status::*;
 pub use theme::*;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::Duration;
+
+	fn chat_data(member_count: i32) -> ChatData {
+		ChatData {
+			chats: vec![Chat {
+				id: "c".to_string(),
+				member_count,
+				..Default::default()
+			}],
+			..Default::default()
+		}
+	}
+
+	fn message(id: &str, sender: &str, timestamp: DateTime<Utc>) -> ChatMessage {
+		ChatMessage {
+			id: id.to_string(),
+			chat_id: "c".to_string(),
+			sender: sender.to_string(),
+			timestamp,
+			..Default::default()
+		}
+	}
+
+	fn ui_state() -> UiState {
+		UiState::with_store(
+			Box::new(MockBackend::new()),
+			MessageStore::open_in_memory().unwrap(),
+		)
+	}
+
+	/// 主窗口没有选中的一个聊天
+	fn other_chat(state: &UiState) -> String {
+		state
+			.chat_data
+			.chats
+			.iter()
+			.find(|chat| chat.id != state.select_chat_id)
+			.map(|chat| chat.id.clone())
+			.unwrap()
+	}
+
+	fn incoming(chat_id: &str, id: &str, text: &str) -> ChatMessage {
+		ChatMessage {
+			id: id.to_string(),
+			chat_id: chat_id.to_string(),
+			sender: "Alice".to_string(),
+			content: MessageContent::text(text),
+			timestamp: Utc::now(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn focused_windows_read_new_messages() {
+		let mut state = ui_state();
+		let chat_id = other_chat(&state);
+		state.focused_windows.insert(chat_id.clone());
+		state.append_message(incoming(&chat_id, "m1", "hi"));
+		assert_eq!(state.chat_data.unread_counts.get(&chat_id).copied().unwrap_or(0), 0);
+		assert!(state.notifications.items.is_empty());
+
+		state.focused_windows.clear();
+		state.append_message(incoming(&chat_id, "m2", "hi"));
+		assert_eq!(state.chat_data.unread_counts[&chat_id], 1);
+		assert_eq!(state.notifications.items.len(), 1);
+	}
+
+	#[test]
+	fn replacing_a_message_bumps_the_revision() {
+		let mut data = chat_data(2);
+		let now = Utc::now();
+		data.add_message("c", message("1", "Alice", now));
+		let revision = data.revision("c");
+		data.add_message("c", message("1", "Alice", now));
+		assert_eq!(data.messages["c"].len(), 1);
+		assert!(data.revision("c") > revision);
+		assert_eq!(data.revision("other"), 0);
+	}
+
+	#[test]
+	fn read_cursor_only_moves_forward() {
+		let mut data = chat_data(3);
+		let now = Utc::now();
+		assert!(data.update_read_cursor("c", "Alice", now));
+		assert!(!data.update_read_cursor("c", "Alice", now));
+		assert!(!data.update_read_cursor("c", "Alice", now - Duration::seconds(1)));
+		assert_eq!(data.read_cursor("c", "Alice"), Some(now));
+		assert_eq!(data.read_cursor("c", "Bob"), None);
+	}
+
+	#[test]
+	fn read_receipt_counts_members_who_read_past_the_message() {
+		let mut data = chat_data(4);
+		let sent_at = Utc::now();
+		let own = message("m", CURRENT_USER, sent_at);
+		data.update_read_cursor("c", "Bob", sent_at);
+		data.update_read_cursor("c", "Alice", sent_at + Duration::seconds(5));
+		data.update_read_cursor("c", "Carol", sent_at - Duration::seconds(5));
+		// 发送者自己的已读位置不算
+		data.update_read_cursor("c", CURRENT_USER, sent_at + Duration::seconds(5));
+
+		let receipt = data.read_receipt("c", &own);
+		assert_eq!(receipt.read_by, vec!["Alice".to_string(), "Bob".to_string()]);
+		assert_eq!(receipt.unread_count, 1);
+		assert_eq!(receipt.label(), "未读 1 人");
+
+		data.update_read_cursor("c", "Carol", sent_at);
+		assert!(data.read_receipt("c", &own).is_all_read());
+	}
+
+	#[test]
+	fn messages_after_the_read_cursor_count_as_unread() {
+		let mut data = chat_data(3);
+		let now = Utc::now();
+		data.update_read_cursor("c", CURRENT_USER, now);
+
+		data.add_message("c", message("old", "Alice", now - Duration::seconds(1)));
+		data.add_message("c", message("own", CURRENT_USER, now + Duration::seconds(1)));
+		assert_eq!(data.unread_counts.get("c").copied().unwrap_or(0), 0);
+
+		data.add_message("c", message("new", "Alice", now + Duration::seconds(2)));
+		assert_eq!(data.unread_counts.get("c"), Some(&1));
+
+		let read_at = data.mark_as_read("c");
+		assert_eq!(data.unread_counts.get("c"), Some(&0));
+		assert_eq!(data.read_cursor("c", CURRENT_USER), Some(read_at));
+		assert!(read_at >= now + Duration::seconds(2));
+	}
+
+	#[test]
+	fn mention_only_chats_count_just_the_mentions() {
+		let mut data = chat_data(3);
+		data.chats[0].notification = NotificationLevel::Mentions;
+		let now = Utc::now();
+		for i in 0..40 {
+			data.add_message("c", message(&i.to_string(), "Alice", now));
+		}
+		assert_eq!(data.notifying_unread("c"), 0);
+
+		let mention = ChatMessage {
+			content: MessageContent::text(format!("@{} look", CURRENT_USER)),
+			..message("m", "Alice", now)
+		};
+		data.add_message("c", mention.clone());
+		// 服务端确认的同一条消息不再计数
+		data.add_message("c", mention);
+		assert_eq!(data.unread_counts["c"], 41);
+		assert_eq!(data.notifying_unread("c"), 1);
+		assert_eq!(data.notifying_unread_total(), 1);
+
+		data.chats[0].notification = NotificationLevel::All;
+		assert_eq!(data.notifying_unread("c"), 41);
+		data.mark_as_read("c");
+		data.chats[0].notification = NotificationLevel::Mentions;
+		assert_eq!(data.notifying_unread("c"), 0);
+	}
+}
@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::ChatMessage;
+
+use super::UiState;
+
+/// 通知中心最多保留的条数
+const MAX_NOTIFICATIONS: usize = 100;
+/// 同时显示的提醒条数
+const MAX_TOASTS: usize = 3;
+const TOAST_SECONDS: i64 = 5;
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub chat_id: String,
+    pub message_id: String,
+    pub chat_name: String,
+    pub sender: String,
+    pub preview: String,
+    pub mentions_me: bool,
+    pub received_at: DateTime<Utc>,
+    pub read: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub message_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// 弹出的提醒和通知中心的列表，只保存在内存里
+#[derive(Debug, Default)]
+pub struct NotificationCenter {
+    /// 新的在前
+    pub items: VecDeque<Notification>,
+    pub toasts: Vec<Toast>,
+    pub show_panel: bool,
+}
+
+impl NotificationCenter {
+    pub fn push(&mut self, notification: Notification, toast: bool) {
+        if self
+            .items
+            .iter()
+            .any(|item| item.message_id == notification.message_id)
+        {
+            return;
+        }
+        if toast {
+            if self.toasts.len() >= MAX_TOASTS {
+                self.toasts.remove(0);
+            }
+            self.toasts.push(Toast {
+                message_id: notification.message_id.clone(),
+                expires_at: notification.received_at + Duration::seconds(TOAST_SECONDS),
+            });
+        }
+        self.items.push_front(notification);
+        self.items.truncate(MAX_NOTIFICATIONS);
+    }
+
+    pub fn get(&self, message_id: &str) -> Option<&Notification> {
+        self.items.iter().find(|item| item.message_id == message_id)
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.items.iter().filter(|item| !item.read).count()
+    }
+
+    pub fn mark_read(&mut self, message_id: &str) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.message_id == message_id) {
+            item.read = true;
+        }
+        self.dismiss_toast(message_id);
+    }
+
+    pub fn mark_all_read(&mut self) {
+        for item in &mut self.items {
+            item.read = true;
+        }
+    }
+
+    /// 打开聊天后这个聊天的通知都算已读
+    pub fn mark_chat_read(&mut self, chat_id: &str) {
+        for item in self.items.iter_mut().filter(|item| item.chat_id == chat_id) {
+            item.read = true;
+        }
+        let items = &self.items;
+        self.toasts
+            .retain(|toast| items.iter().any(|item| item.message_id == toast.message_id && !item.read));
+    }
+
+    pub fn dismiss_toast(&mut self, message_id: &str) {
+        self.toasts.retain(|toast| toast.message_id != message_id);
+    }
+
+    /// 去掉到期的提醒，返回下一条到期的时间
+    pub fn expire_toasts(&mut self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.toasts.retain(|toast| toast.expires_at > now);
+        self.toasts.iter().map(|toast| toast.expires_at).min()
+    }
+}
+
+impl UiState {
+    /// 收到别人发的消息后记一条通知。免打扰的聊天只记 @我 的，自己免打扰时不弹出
+    pub(super) fn notify(&mut self, message: &ChatMessage) {
+        if message.is_own() || self.is_reading(&message.chat_id) {
+            return;
+        }
+        let Some(chat) = self.chat_data.chat(&message.chat_id) else {
+            return;
+        };
+        let mentions_me = message.mentions_me();
+        let notifies = chat.notification.notifies(mentions_me);
+        if !notifies && !mentions_me {
+            return;
+        }
+        let notification = Notification {
+            chat_id: message.chat_id.clone(),
+            message_id: message.id.clone(),
+            chat_name: chat.name.clone(),
+            sender: message.sender.clone(),
+            preview: message.preview(),
+            mentions_me,
+            received_at: Utc::now(),
+            read: false,
+        };
+        let toast = notifies && !self.is_do_not_disturb();
+        self.notifications.push(notification, toast);
+    }
+
+    /// 点击提醒或通知，跳到对应的消息
+    pub fn open_notification(&mut self, message_id: &str) {
+        let Some(notification) = self.notifications.get(message_id).cloned() else {
+            return;
+        };
+        self.notifications.mark_read(message_id);
+        self.selected_nav_index = 1;
+        self.select_chat(&notification.chat_id);
+        self.reveal_message(&notification.chat_id, message_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::MockBackend, store::MessageStore, MessageContent, NotificationLevel, UserStatus,
+        CURRENT_USER,
+    };
+
+    fn notification(chat_id: &str, message_id: &str, received_at: DateTime<Utc>) -> Notification {
+        Notification {
+            chat_id: chat_id.to_string(),
+            message_id: message_id.to_string(),
+            chat_name: String::new(),
+            sender: "Alice".to_string(),
+            preview: String::new(),
+            mentions_me: false,
+            received_at,
+            read: false,
+        }
+    }
+
+    fn toast_ids(center: &NotificationCenter) -> Vec<&str> {
+        center
+            .toasts
+            .iter()
+            .map(|toast| toast.message_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn push_skips_duplicates_and_keeps_the_newest_toasts() {
+        let now = Utc::now();
+        let mut center = NotificationCenter::default();
+        center.push(notification("c", "1", now), true);
+        center.push(notification("c", "1", now), true);
+        assert_eq!(center.items.len(), 1);
+        assert_eq!(center.toasts.len(), 1);
+
+        for id in 2..=4 {
+            center.push(notification("c", &id.to_string(), now), true);
+        }
+        assert_eq!(toast_ids(&center), ["2", "3", "4"]);
+        assert_eq!(center.items.front().unwrap().message_id, "4");
+
+        for id in 5..MAX_NOTIFICATIONS + 10 {
+            center.push(notification("c", &id.to_string(), now), false);
+        }
+        assert_eq!(center.items.len(), MAX_NOTIFICATIONS);
+        assert_eq!(center.unread_count(), MAX_NOTIFICATIONS);
+        assert!(center.get("1").is_none());
+    }
+
+    #[test]
+    fn reading_a_chat_only_drops_its_toasts() {
+        let now = Utc::now();
+        let mut center = NotificationCenter::default();
+        center.push(notification("a", "1", now), true);
+        center.push(notification("b", "2", now), true);
+        center.mark_chat_read("a");
+        assert_eq!(toast_ids(&center), ["2"]);
+        assert!(center.get("1").unwrap().read);
+        assert!(!center.get("2").unwrap().read);
+    }
+
+    #[test]
+    fn expiring_toasts_returns_the_next_deadline() {
+        let now = Utc::now();
+        let mut center = NotificationCenter::default();
+        center.push(notification("c", "1", now), true);
+        center.push(notification("c", "2", now + Duration::seconds(2)), true);
+        let first = now + Duration::seconds(TOAST_SECONDS);
+        assert_eq!(center.expire_toasts(now), Some(first));
+        assert_eq!(
+            center.expire_toasts(first),
+            Some(first + Duration::seconds(2))
+        );
+        assert_eq!(toast_ids(&center), ["2"]);
+        assert_eq!(center.expire_toasts(first + Duration::seconds(2)), None);
+        assert!(center.toasts.is_empty());
+    }
+
+    #[test]
+    fn notify_follows_chat_levels_and_do_not_disturb() {
+        let mut state = UiState::with_store(
+            Box::new(MockBackend::new()),
+            MessageStore::open_in_memory().unwrap(),
+        );
+        let chat_id = state
+            .chat_data
+            .chats
+            .iter()
+            .find(|chat| chat.id != state.select_chat_id)
+            .map(|chat| chat.id.clone())
+            .unwrap();
+        let message = |id: &str, text: &str| ChatMessage {
+            id: id.to_string(),
+            chat_id: chat_id.clone(),
+            sender: "Alice".to_string(),
+            content: MessageContent::text(text),
+            timestamp: Utc::now(),
+            ..Default::default()
+        };
+        let set_level = |state: &mut UiState, level: NotificationLevel| {
+            for chat in state.chat_data.chats.iter_mut().filter(|chat| chat.id == chat_id) {
+                chat.notification = level;
+            }
+        };
+        let mention = format!("@{} look", CURRENT_USER);
+
+        set_level(&mut state, NotificationLevel::None);
+        state.notify(&message("1", "hi"));
+        assert!(state.notifications.items.is_empty());
+        // 免打扰的聊天里 @我 的只记到通知中心，不弹出
+        state.notify(&message("2", &mention));
+        assert_eq!(state.notifications.items.len(), 1);
+        assert!(state.notifications.toasts.is_empty());
+
+        set_level(&mut state, NotificationLevel::Mentions);
+        state.notify(&message("3", "hi"));
+        assert_eq!(state.notifications.items.len(), 1);
+        state.notify(&message("4", &mention));
+        assert_eq!(toast_ids(&state.notifications), ["4"]);
+        assert!(state.notifications.get("4").unwrap().mentions_me);
+
+        set_level(&mut state, NotificationLevel::All);
+        state.user_status = Some(UserStatus {
+            icon: "⛔".to_string(),
+            text: "专注".to_string(),
+            expires_at: None,
+            do_not_disturb: true,
+        });
+        state.notify(&message("5", "hi"));
+        assert!(state.notifications.get("5").is_some());
+        assert_eq!(toast_ids(&state.notifications), ["4"]);
+
+        // 自己发的消息不提醒
+        state.notify(&ChatMessage {
+            sender: CURRENT_USER.to_string(),
+            ..message("6", "hi")
+        });
+        assert!(state.notifications.get("6").is_none());
+    }
+}
@@ -4,11 +4,17 @@ use bevy_egui::{
 };
 
 pub fn setup_ui(mut context: EguiContexts) {
-    let ctx = context.ctx_mut();
+    setup_context(context.ctx_mut());
+}
+
+/// 字体和样式，每个窗口的 egui 上下文都要设置一次
+pub fn setup_context(ctx: &egui::Context) {
     setup_fonts(ctx);
     setup_style(ctx);
     ctx.system_theme();
     setup_common_visuals(ctx);
+    // 文件卡片的 SVG 图标
+    egui_extras::install_image_loaders(ctx);
 }
 
 pub fn setup_fonts(ctx: &egui::Context) {
@@ -0,0 +1,232 @@
+use bevy::{log::warn, prelude::ResMut};
+use bevy_egui::EguiContexts;
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+
+use crate::UserStatus;
+
+use super::UiState;
+
+/// 状态的持续时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusDuration {
+    #[default]
+    OneHour,
+    /// 到今天的某个时刻，已经过了就到明天的这个时刻
+    Until(u32),
+    AllDay,
+    Forever,
+}
+
+impl StatusDuration {
+    pub const CHOICES: [StatusDuration; 4] = [
+        StatusDuration::OneHour,
+        StatusDuration::Until(17),
+        StatusDuration::AllDay,
+        StatusDuration::Forever,
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            StatusDuration::OneHour => "1 hour".to_string(),
+            StatusDuration::Until(hour) => format!("Until {}:00", hour),
+            StatusDuration::AllDay => "All day".to_string(),
+            StatusDuration::Forever => "Don't clear".to_string(),
+        }
+    }
+
+    pub fn expires_at(&self, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+        let at_hour = |hour: u32| {
+            let time = NaiveTime::from_hms_opt(hour.min(23), 0, 0).unwrap_or_default();
+            let mut date = now.date_naive();
+            if now.time() >= time {
+                date = date.succ_opt().unwrap_or(date);
+            }
+            Local
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+        };
+        match self {
+            StatusDuration::OneHour => Some((now + Duration::hours(1)).with_timezone(&Utc)),
+            StatusDuration::Until(hour) => at_hour(*hour),
+            // 到今天结束
+            StatusDuration::AllDay => {
+                let midnight = now.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                Local
+                    .from_local_datetime(&midnight)
+                    .earliest()
+                    .map(|time| time.with_timezone(&Utc))
+            }
+            StatusDuration::Forever => None,
+        }
+    }
+}
+
+/// 状态菜单里的预设
+pub struct StatusPreset {
+    pub icon: &'static str,
+    pub text: &'static str,
+    pub duration: StatusDuration,
+    pub do_not_disturb: bool,
+}
+
+impl StatusPreset {
+    pub fn to_status(&self, now: DateTime<Local>) -> UserStatus {
+        UserStatus {
+            icon: self.icon.to_string(),
+            text: self.text.to_string(),
+            expires_at: self.duration.expires_at(now),
+            do_not_disturb: self.do_not_disturb,
+        }
+    }
+}
+
+pub const STATUS_PRESETS: &[StatusPreset] = &[
+    StatusPreset {
+        icon: "🔕",
+        text: "Do Not Disturb",
+        duration: StatusDuration::Until(17),
+        do_not_disturb: true,
+    },
+    StatusPreset {
+        icon: "📅",
+        text: "In a meeting",
+        duration: StatusDuration::OneHour,
+        do_not_disturb: false,
+    },
+    StatusPreset {
+        icon: "🏠",
+        text: "Working from home",
+        duration: StatusDuration::AllDay,
+        do_not_disturb: false,
+    },
+];
+
+/// 自定义状态的编辑框
+#[derive(Debug, Clone, Default)]
+pub struct StatusDraft {
+    pub open: bool,
+    pub icon: String,
+    pub text: String,
+    pub duration: StatusDuration,
+    pub do_not_disturb: bool,
+}
+
+impl StatusDraft {
+    /// 打开编辑框并填入当前的状态，持续时间重新选
+    pub fn edit(status: Option<&UserStatus>) -> Self {
+        let mut draft = Self {
+            open: true,
+            ..Default::default()
+        };
+        if let Some(status) = status {
+            draft.icon = status.icon.clone();
+            draft.text = status.text.clone();
+            draft.do_not_disturb = status.do_not_disturb;
+        }
+        draft
+    }
+
+    pub fn to_status(&self, now: DateTime<Local>) -> Option<UserStatus> {
+        let text = self.text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        let icon = self.icon.trim();
+        Some(UserStatus {
+            icon: if icon.is_empty() { "💬" } else { icon }.to_string(),
+            text: text.to_string(),
+            expires_at: self.duration.expires_at(now),
+            do_not_disturb: self.do_not_disturb,
+        })
+    }
+}
+
+/// 状态到期后自动清除。事件循环可能在休眠，按到期时间预约一次唤醒
+pub fn expire_user_status(mut ui_state: ResMut<UiState>, mut contexts: EguiContexts) {
+    let Some(delay) = ui_state
+        .user_status
+        .as_ref()
+        .and_then(|status| status.time_left(Utc::now()))
+    else {
+        return;
+    };
+    if delay.is_zero() {
+        ui_state.set_user_status(None);
+        return;
+    }
+    contexts.ctx_mut().request_repaint_after(delay);
+}
+
+impl UiState {
+    pub fn set_user_status(&mut self, status: Option<UserStatus>) {
+        if let Err(err) = self.store.save_user_status(status.as_ref()) {
+            warn!("failed to save user status: {}", err);
+        }
+        self.user_status = status;
+    }
+
+    /// 免打扰时不弹出任何提醒
+    pub fn is_do_not_disturb(&self) -> bool {
+        self.user_status
+            .as_ref()
+            .is_some_and(|status| status.do_not_disturb && !status.is_expired(Utc::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 6, day, hour, minute, 0).unwrap()
+    }
+
+    fn expires_at(duration: StatusDuration, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        duration
+            .expires_at(now)
+            .map(|time| time.with_timezone(&Local))
+    }
+
+    #[test]
+    fn until_rolls_over_to_tomorrow_once_passed() {
+        let until = StatusDuration::Until(17);
+        assert_eq!(expires_at(until, local(3, 10, 30)), Some(local(3, 17, 0)));
+        assert_eq!(expires_at(until, local(3, 17, 0)), Some(local(4, 17, 0)));
+        assert_eq!(expires_at(until, local(3, 18, 15)), Some(local(4, 17, 0)));
+    }
+
+    #[test]
+    fn other_durations() {
+        let now = local(3, 10, 30);
+        assert_eq!(expires_at(StatusDuration::OneHour, now), Some(local(3, 11, 30)));
+        assert_eq!(expires_at(StatusDuration::AllDay, now), Some(local(4, 0, 0)));
+        assert_eq!(StatusDuration::Forever.expires_at(now), None);
+    }
+
+    #[test]
+    fn draft_needs_text_and_defaults_the_icon() {
+        let now = local(3, 10, 30);
+        let mut draft = StatusDraft {
+            text: "  ".to_string(),
+            duration: StatusDuration::Forever,
+            ..Default::default()
+        };
+        assert_eq!(draft.to_status(now), None);
+
+        draft.text = " Focusing ".to_string();
+        let status = draft.to_status(now).unwrap();
+        assert_eq!((status.icon.as_str(), status.text.as_str()), ("💬", "Focusing"));
+        assert_eq!(status.expires_at, None);
+    }
+
+    #[test]
+    fn editing_keeps_the_current_status() {
+        let status = STATUS_PRESETS[0].to_status(local(3, 10, 30));
+        let draft = StatusDraft::edit(Some(&status));
+        assert!(draft.open);
+        assert_eq!(draft.text, "Do Not Disturb");
+        assert!(draft.do_not_disturb);
+        assert!(StatusDraft::edit(None).text.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+mod query;
+mod tokenize;
+
+pub use query::*;
+pub use tokenize::*;
+
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+
+/// 每类结果最多显示的条数
+pub const RESULTS_PER_KIND: usize = 20;
+
+/// 摘要里命中位置之前保留的字数
+const SNIPPET_BEFORE: usize = 16;
+const SNIPPET_LENGTH: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchKind {
+    Chat,
+    Contact,
+    Message,
+    File,
+}
+
+impl SearchKind {
+    pub const ALL: [SearchKind; 4] = [
+        SearchKind::Chat,
+        SearchKind::Contact,
+        SearchKind::Message,
+        SearchKind::File,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchKind::Chat => "会话",
+            SearchKind::Contact => "联系人",
+            SearchKind::Message => "消息",
+            SearchKind::File => "文件",
+        }
+    }
+}
+
+/// 索引里的一条记录。消息和文件的 `ref_id` 是消息 id，会话是聊天 id，联系人是名字
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub ref_id: String,
+    pub chat_id: String,
+    pub title: String,
+    pub body: String,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// 一段摘要，`highlights` 是需要高亮的字节范围
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// 按类别分组的搜索结果
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    /// 搜索框里的原始输入
+    pub input: String,
+    pub sort: SearchSort,
+    pub terms: Vec<String>,
+    pub groups: Vec<(SearchKind, Vec<SearchHit>)>,
+}
+
+impl SearchResults {
+    pub fn is_empty(&self) -> bool {
+        self.groups.iter().all(|(_, hits)| hits.is_empty())
+    }
+}
+
+/// 聊天内搜索最多找多少条
+pub const CHAT_SEARCH_LIMIT: usize = 1000;
+
+/// 聊天内搜索的状态，`matches` 是命中的消息 id，按时间从旧到新排列
+#[derive(Debug, Clone, Default)]
+pub struct ChatSearch {
+    pub open: bool,
+    pub text: String,
+    /// 上次查询时的 (聊天 id, 输入)，变化时重新查询
+    pub searched: Option<(String, String)>,
+    pub terms: Vec<String>,
+    pub matches: Vec<String>,
+    pub current: Option<usize>,
+}
+
+impl ChatSearch {
+    /// 例如 `3/17`，从最新的一条开始数
+    pub fn position_label(&self) -> String {
+        match self.current {
+            Some(index) => format!("{}/{}", self.matches.len() - index, self.matches.len()),
+            None if self.text.trim().is_empty() => String::new(),
+            None => "无结果".to_string(),
+        }
+    }
+
+    /// 移到更早或更新的一条命中，返回新的消息 id
+    pub fn step(&mut self, older: bool) -> Option<String> {
+        let current = self.current?;
+        let next = if older {
+            current.checked_sub(1)?
+        } else {
+            current + 1
+        };
+        let id = self.matches.get(next)?.clone();
+        self.current = Some(next);
+        Some(id)
+    }
+}
+
+/// 整段文字里所有命中的位置
+pub fn highlight(text: &str, terms: &[String]) -> Snippet {
+    let chars: Vec<char> = text.chars().collect();
+    excerpt(&chars, find_matches(&chars, terms), 0..chars.len(), false)
+}
+
+/// 截取第一个命中位置附近的一段文字，并标出所有命中的词
+pub fn snippet(text: &str, terms: &[String]) -> Snippet {
+    let chars: Vec<char> = text.chars().collect();
+    let matches = find_matches(&chars, terms);
+    let start = matches
+        .first()
+        .map_or(0, |first| first.start.saturating_sub(SNIPPET_BEFORE));
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+    excerpt(&chars, matches, start..end, true)
+}
+
+/// 命中的字符范围，不区分大小写，重叠时取最长的词
+fn find_matches(chars: &[char], terms: &[String]) -> Vec<Range<usize>> {
+    let folded: Vec<char> = chars.iter().map(|c| fold_char(*c)).collect();
+    let terms: Vec<Vec<char>> = terms
+        .iter()
+        .filter(|term| !term.is_empty())
+        .map(|term| term.chars().map(fold_char).collect())
+        .collect();
+
+    let mut matches = Vec::new();
+    let mut index = 0;
+    while index < folded.len() {
+        let hit = terms
+            .iter()
+            .filter(|term| folded[index..].starts_with(term))
+            .map(|term| term.len())
+            .max();
+        match hit {
+            Some(len) => {
+                matches.push(index..index + len);
+                index += len;
+            }
+            None => index += 1,
+        }
+    }
+    matches
+}
+
+/// 取 `chars[window]`，把字符范围换成摘要里的字节范围
+fn excerpt(
+    chars: &[char],
+    matches: Vec<Range<usize>>,
+    window: Range<usize>,
+    single_line: bool,
+) -> Snippet {
+    let Range { start, end } = window;
+    let mut snippet = Snippet::default();
+    if start > 0 {
+        snippet.text.push('…');
+    }
+    let mut byte_offsets = Vec::with_capacity(end - start + 1);
+    for c in &chars[start..end] {
+        byte_offsets.push(snippet.text.len());
+        snippet.text.push(if single_line && c.is_whitespace() { ' ' } else { *c });
+    }
+    byte_offsets.push(snippet.text.len());
+    if end < chars.len() {
+        snippet.text.push('…');
+    }
+
+    snippet.highlights = matches
+        .into_iter()
+        .filter(|range| range.start >= start && range.end <= end)
+        .map(|range| byte_offsets[range.start - start]..byte_offsets[range.end - start])
+        .collect();
+    snippet
+}
+
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlighted(snippet: &Snippet) -> Vec<&str> {
+        snippet
+            .highlights
+            .iter()
+            .map(|range| &snippet.text[range.clone()])
+            .collect()
+    }
+
+    #[test]
+    fn snippet_highlights_every_match_ignoring_case() {
+        let snippet = snippet("Deploy the build, then DEPLOY docs", &["deploy".to_string()]);
+        assert_eq!(snippet.text, "Deploy the build, then DEPLOY docs");
+        assert_eq!(highlighted(&snippet), ["Deploy", "DEPLOY"]);
+    }
+
+    #[test]
+    fn snippet_centers_on_the_first_match() {
+        let text = format!("{}站会改期到周五\n请大家留意", "很长的开头".repeat(10));
+        let snippet = snippet(&text, &["站会".to_string()]);
+        assert!(snippet.text.starts_with('…'));
+        assert!(!snippet.text.contains('\n'));
+        assert_eq!(highlighted(&snippet), ["站会"]);
+        assert_eq!(snippet.text.chars().filter(|c| *c != '…').count(), 16 + 2 + 11);
+    }
+
+    #[test]
+    fn snippet_prefers_the_longest_term() {
+        let terms = ["站".to_string(), "站会".to_string()];
+        let snippet = snippet("明天站会", &terms);
+        assert_eq!(highlighted(&snippet), ["站会"]);
+    }
+
+    #[test]
+    fn snippet_without_matches_starts_at_the_beginning() {
+        let snippet = snippet("nothing here", &["deploy".to_string()]);
+        assert_eq!(snippet.text, "nothing here");
+        assert!(snippet.highlights.is_empty());
+    }
+}
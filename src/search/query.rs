@@ -0,0 +1,264 @@
+use chrono::{DateTime, Days, Local, NaiveDate, TimeZone, Utc};
+
+use crate::MessageType;
+
+use super::{match_expression, query_terms};
+
+/// 搜索结果的排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Newest,
+}
+
+impl SearchSort {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchSort::Relevance => "相关度",
+            SearchSort::Newest => "最新",
+        }
+    }
+}
+
+/// 解析后的搜索语句，例如 `from:Ray in:"Group 1 Chat" has:file after:2024-01-01 "站会 改期"`
+///
+/// - `from:` 发送者，不区分大小写
+/// - `in:` 会话名里包含这段文字
+/// - `has:file` / `has:code` / `has:image` 消息类型，可以写多个
+/// - `before:` / `after:` 日期（`2024-01-31` 或 `2024/01/31`），都不包含当天
+/// - 引号里的内容按整句匹配，其余的词都要出现
+///
+/// 无法识别的过滤条件按普通文字处理。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub words: Vec<String>,
+    pub phrases: Vec<String>,
+    pub from: Option<String>,
+    pub chat: Option<String>,
+    pub has: Vec<MessageType>,
+    pub before: Option<NaiveDate>,
+    pub after: Option<NaiveDate>,
+    /// 限定在某个聊天里，不由语句解析，聊天内搜索时设置
+    pub chat_id: Option<String>,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Self::default();
+        let mut chars = input.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            if chars.next_if_eq(&'"').is_some() {
+                let phrase = read_quoted(&mut chars);
+                if !phrase.trim().is_empty() {
+                    query.phrases.push(phrase.trim().to_string());
+                }
+                continue;
+            }
+
+            let mut token = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+                // `key:"带空格的值"`
+                if c == ':' && chars.peek() == Some(&'"') && is_filter_key(&token[..token.len() - 1]) {
+                    chars.next();
+                    token.push_str(&read_quoted(&mut chars));
+                    break;
+                }
+            }
+            if !query.apply_filter(&token) {
+                query.words.push(token);
+            }
+        }
+        query
+    }
+
+    fn apply_filter(&mut self, token: &str) -> bool {
+        let Some((key, value)) = token.split_once(':') else {
+            return false;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return false;
+        }
+        match key.to_lowercase().as_str() {
+            "from" => self.from = Some(value.to_string()),
+            "in" => self.chat = Some(value.to_string()),
+            "has" => match value.to_lowercase().as_str() {
+                "file" => self.has.push(MessageType::File),
+                "code" => self.has.push(MessageType::Code),
+                "image" | "images" => self.has.push(MessageType::Images),
+                _ => return false,
+            },
+            "before" => match parse_date(value) {
+                Some(date) => self.before = Some(date),
+                None => return false,
+            },
+            "after" => match parse_date(value) {
+                Some(date) => self.after = Some(date),
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.phrases.is_empty() && !self.has_filters()
+    }
+
+    /// 是否有只对消息生效的过滤条件，有的话不再搜索会话和联系人
+    pub fn has_filters(&self) -> bool {
+        self.from.is_some()
+            || self.chat.is_some()
+            || self.chat_id.is_some()
+            || !self.has.is_empty()
+            || self.before.is_some()
+            || self.after.is_some()
+    }
+
+    /// 全文索引的 MATCH 表达式，引号里的整句由 `matches_phrases` 再核对一次
+    pub fn match_expression(&self) -> Option<String> {
+        let text: Vec<&str> = self
+            .phrases
+            .iter()
+            .chain(self.words.iter())
+            .map(String::as_str)
+            .collect();
+        match_expression(&text.join(" "))
+    }
+
+    /// 用来高亮的词，整句作为一个整体
+    pub fn highlight_terms(&self) -> Vec<String> {
+        let mut terms: Vec<String> = self
+            .phrases
+            .iter()
+            .map(|phrase| phrase.to_lowercase())
+            .collect();
+        terms.extend(self.words.iter().flat_map(|word| query_terms(word)));
+        terms
+    }
+
+    /// 发送时间需要早于这个时间
+    pub fn sent_before(&self) -> Option<DateTime<Utc>> {
+        self.before.map(start_of_day)
+    }
+
+    /// 发送时间不早于这个时间，即 `after` 的第二天零点
+    pub fn sent_after(&self) -> Option<DateTime<Utc>> {
+        self.after
+            .and_then(|date| date.checked_add_days(Days::new(1)))
+            .map(start_of_day)
+    }
+
+    pub fn matches_phrases(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        self.phrases
+            .iter()
+            .all(|phrase| text.contains(&phrase.to_lowercase()))
+    }
+}
+
+fn is_filter_key(key: &str) -> bool {
+    matches!(key.to_lowercase().as_str(), "from" | "in")
+}
+
+/// 读到下一个引号为止，开头的引号已经读掉
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            break;
+        }
+        text.push(c);
+    }
+    text
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y/%m/%d"))
+        .ok()
+}
+
+/// 本地时区这一天的零点
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    #[test]
+    fn parses_filters_words_and_phrases() {
+        let query = SearchQuery::parse(r#"from:Ray in:"Group 1 Chat" has:file "站会 改期" notes"#);
+        assert_eq!(
+            query,
+            SearchQuery {
+                words: vec!["notes".to_string()],
+                phrases: vec!["站会 改期".to_string()],
+                from: Some("Ray".to_string()),
+                chat: Some("Group 1 Chat".to_string()),
+                has: vec![MessageType::File],
+                ..Default::default()
+            }
+        );
+        assert!(query.has_filters());
+    }
+
+    #[test]
+    fn parses_dates_in_both_formats() {
+        let query = SearchQuery::parse("before:2024-01-31 after:2024/01/01");
+        assert_eq!(query.before, date(2024, 1, 31));
+        assert_eq!(query.after, date(2024, 1, 1));
+        assert!(query.words.is_empty());
+        // 两个时间都不包含当天
+        assert!(query.sent_after().unwrap() > start_of_day(date(2024, 1, 1).unwrap()));
+        assert_eq!(query.sent_before(), Some(start_of_day(date(2024, 1, 31).unwrap())));
+    }
+
+    #[test]
+    fn unknown_or_invalid_filters_are_plain_words() {
+        let query = SearchQuery::parse("has:video before:tomorrow http://example.com from:");
+        assert_eq!(
+            query.words,
+            ["has:video", "before:tomorrow", "http://example.com", "from:"]
+        );
+        assert!(!query.has_filters());
+    }
+
+    #[test]
+    fn filter_keys_are_case_insensitive() {
+        let query = SearchQuery::parse("FROM:alice Has:Images");
+        assert_eq!(query.from.as_deref(), Some("alice"));
+        assert_eq!(query.has, [MessageType::Images]);
+    }
+
+    #[test]
+    fn unterminated_quote_runs_to_the_end() {
+        let query = SearchQuery::parse(r#"deploy "release notes"#);
+        assert_eq!(query.words, ["deploy"]);
+        assert_eq!(query.phrases, ["release notes"]);
+        assert!(query.matches_phrases("Release Notes for v2"));
+        assert!(!query.matches_phrases("release the notes"));
+        assert_eq!(
+            query.match_expression(),
+            Some("\"release\" AND \"notes\" AND \"deploy\"*".to_string())
+        );
+    }
+}
@@ -0,0 +1,129 @@
+/// 中日韩文字之间没有空格，需要按字切分
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // 平假名、片假名
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xAC00..=0xD7AF // 谚文
+        | 0xF900..=0xFAFF
+        | 0x20000..=0x2FA1F)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// 连续的字母或数字，已转为小写
+    Word(String),
+    /// 连续的中日韩文字
+    Cjk(Vec<char>),
+}
+
+fn segments(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current: Option<Segment> = None;
+    for c in text.chars() {
+        if is_cjk(c) {
+            if let Some(Segment::Cjk(chars)) = &mut current {
+                chars.push(c);
+                continue;
+            }
+            segments.extend(current.replace(Segment::Cjk(vec![c])));
+        } else if c.is_alphanumeric() {
+            if let Some(Segment::Word(word)) = &mut current {
+                word.extend(c.to_lowercase());
+                continue;
+            }
+            segments.extend(current.replace(Segment::Word(c.to_lowercase().collect())));
+        } else {
+            segments.extend(current.take());
+        }
+    }
+    segments.extend(current);
+    segments
+}
+
+/// 建索引用的词：英文按单词切分，中日韩文字同时收录单字和相邻的两字
+pub fn index_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for segment in segments(text) {
+        match segment {
+            Segment::Word(word) => tokens.push(word),
+            Segment::Cjk(chars) => {
+                tokens.extend(chars.iter().map(|c| c.to_string()));
+                tokens.extend(chars.windows(2).map(|pair| pair.iter().collect()));
+            }
+        }
+    }
+    tokens
+}
+
+/// 写入全文索引的文本，词之间用空格隔开
+pub fn index_text(text: &str) -> String {
+    index_tokens(text).join(" ")
+}
+
+/// 查询里的词，用来在结果里高亮
+pub fn query_terms(query: &str) -> Vec<String> {
+    segments(query)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Word(word) => word,
+            Segment::Cjk(chars) => chars.into_iter().collect(),
+        })
+        .collect()
+}
+
+/// 转换成 FTS5 的 MATCH 表达式：所有词都要出现，最后一个英文词按前缀匹配，
+/// 多个汉字拆成相邻的两字组合
+pub fn match_expression(query: &str) -> Option<String> {
+    let segments = segments(query);
+    let last = segments.len().saturating_sub(1);
+    let mut terms = Vec::new();
+    for (index, segment) in segments.into_iter().enumerate() {
+        match segment {
+            Segment::Word(word) if index == last => terms.push(format!("\"{}\"*", word)),
+            Segment::Word(word) => terms.push(format!("\"{}\"", word)),
+            Segment::Cjk(chars) if chars.len() == 1 => terms.push(format!("\"{}\"", chars[0])),
+            Segment::Cjk(chars) => terms.extend(
+                chars
+                    .windows(2)
+                    .map(|pair| format!("\"{}\"", pair.iter().collect::<String>())),
+            ),
+        }
+    }
+    (!terms.is_empty()).then(|| terms.join(" AND "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_tokens_split_words_and_cjk_pairs() {
+        assert_eq!(index_tokens("Hello, World"), ["hello", "world"]);
+        assert_eq!(index_tokens("站会改期"), ["站", "会", "改", "期", "站会", "会改", "改期"]);
+        assert_eq!(index_tokens("v2版本"), ["v2", "版", "本", "版本"]);
+        assert!(index_tokens("  ...  ").is_empty());
+    }
+
+    #[test]
+    fn match_expression_requires_every_term() {
+        assert_eq!(match_expression("deploy"), Some("\"deploy\"*".to_string()));
+        assert_eq!(
+            match_expression("Release notes"),
+            Some("\"release\" AND \"notes\"*".to_string())
+        );
+        assert_eq!(
+            match_expression("站会改期"),
+            Some("\"站会\" AND \"会改\" AND \"改期\"".to_string())
+        );
+        assert_eq!(match_expression("会"), Some("\"会\"".to_string()));
+        // 引号之类的符号不会进入表达式
+        assert_eq!(match_expression("\"a\" OR"), Some("\"a\" AND \"or\"*".to_string()));
+        assert_eq!(match_expression("!!"), None);
+    }
+
+    #[test]
+    fn query_terms_keep_cjk_runs_whole() {
+        assert_eq!(query_terms("周五 Standup"), ["周五", "standup"]);
+    }
+}
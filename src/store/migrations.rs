@@ -0,0 +1,174 @@
+use rusqlite::Connection;
+
+use super::StoreError;
+
+/// 按顺序执行的建表/升级脚本，数据库当前版本记录在 `PRAGMA user_version` 中。
+/// 只能在末尾追加新的迁移，已经发布的脚本不要修改。
+/// v1 到 v11 是第一次发布前整理过的版本，之前开发版本建的库（`user_version` 可能到 14）无法升级，需要删掉重建。
+const MIGRATIONS: &[&str] = &[
+    // v1: 聊天、消息、未读数
+    "CREATE TABLE chats (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        avatar TEXT NOT NULL,
+        member_count INTEGER NOT NULL DEFAULT 0,
+        last_message TEXT,
+        chat_type TEXT NOT NULL,
+        pin INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE messages (
+        id TEXT PRIMARY KEY NOT NULL,
+        chat_id TEXT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        sender TEXT NOT NULL,
+        avatar TEXT NOT NULL,
+        content TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        message_type TEXT NOT NULL
+    );
+    CREATE INDEX idx_messages_chat ON messages(chat_id);
+    CREATE TABLE unread_counts (
+        chat_id TEXT PRIMARY KEY NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        count INTEGER NOT NULL DEFAULT 0
+    );",
+    // v2: 时间改为 UTC 毫秒时间戳。旧数据是本地时间的字符串，分隔符可能是 `-` 或 `.`
+    "ALTER TABLE messages ADD COLUMN sent_at INTEGER NOT NULL DEFAULT 0;
+    UPDATE messages SET sent_at = COALESCE(
+        CAST(strftime('%s', replace(substr(timestamp, 1, 10), '.', '-') || substr(timestamp, 11), 'utc') AS INTEGER) * 1000,
+        0
+    );
+    ALTER TABLE messages DROP COLUMN timestamp;
+    CREATE INDEX idx_messages_chat_time ON messages(chat_id, sent_at);",
+    // v3: 客户端幂等键
+    "ALTER TABLE messages ADD COLUMN client_key TEXT NOT NULL DEFAULT '';
+    CREATE UNIQUE INDEX idx_messages_client_key ON messages(client_key) WHERE client_key != '';",
+    // v4: 投递状态和待发送队列
+    "ALTER TABLE messages ADD COLUMN delivery TEXT NOT NULL DEFAULT 'sent';
+    CREATE TABLE outbox (
+        client_key TEXT PRIMARY KEY NOT NULL,
+        message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    );",
+    // v5: 每个成员在每个聊天里的已读位置
+    "CREATE TABLE read_cursors (
+        chat_id TEXT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        user TEXT NOT NULL,
+        read_at INTEGER NOT NULL,
+        PRIMARY KEY (chat_id, user)
+    );",
+    // v6: 全文索引。`tokens` 是切好词的文本，由程序写入，中文按单字和两字组合切分。
+    // 索引行的 rowid 就是 `search_refs.id`，按类别和 id 查找索引行时走 `search_refs` 的唯一索引
+    "CREATE TABLE search_refs (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        ref_id TEXT NOT NULL,
+        UNIQUE (kind, ref_id)
+    );
+    CREATE VIRTUAL TABLE search_index USING fts5(
+        tokens,
+        chat_id UNINDEXED,
+        title UNINDEXED,
+        body UNINDEXED,
+        sent_at UNINDEXED
+    );",
+    // v7: 聊天的标记、完成状态、标签和云文档会话，标签用换行分隔
+    "ALTER TABLE chats ADD COLUMN flagged INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE chats ADD COLUMN done INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE chats ADD COLUMN tags TEXT NOT NULL DEFAULT '';
+    ALTER TABLE chats ADD COLUMN cloud_doc INTEGER NOT NULL DEFAULT 0;",
+    // v8: 话题群里的回复指向所属话题的第一条消息
+    "ALTER TABLE messages ADD COLUMN reply_to TEXT;",
+    // v9: 右键菜单里的通知级别、固定到导航栏、标为未读、独立窗口
+    "ALTER TABLE chats ADD COLUMN notification TEXT NOT NULL DEFAULT 'all';
+    ALTER TABLE chats ADD COLUMN nav_pinned INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE chats ADD COLUMN marked_unread INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE chats ADD COLUMN detached INTEGER NOT NULL DEFAULT 0;",
+    // v10: 自己的状态，只有一行
+    "CREATE TABLE user_status (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        icon TEXT NOT NULL,
+        text TEXT NOT NULL,
+        expires_at INTEGER,
+        do_not_disturb INTEGER NOT NULL DEFAULT 0
+    );",
+    // v11: 消息内容改为分段的 JSON。message_type 列保留，由内容推出，搜索的 `has:` 条件直接按列过滤
+    "UPDATE messages SET content = json_object(
+        'v', 1,
+        'segments', json_array(CASE message_type
+            WHEN 'code' THEN json_object('type', 'code', 'code', content)
+            WHEN 'images' THEN json_object('type', 'image', 'path', content)
+            WHEN 'file' THEN json_object('type', 'file', 'attachment', json_object(
+                'name', content, 'size', 0, 'mime', 'application/octet-stream',
+                'sha256', '', 'path', ''
+            ))
+            ELSE json_object('type', 'text', 'text', content)
+        END)
+    );",
+];
+
+pub fn schema_version(conn: &Connection) -> Result<usize, StoreError> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+pub fn run_migrations(conn: &mut Connection) -> Result<(), StoreError> {
+    let current = schema_version(conn)?;
+    if current > MIGRATIONS.len() {
+        return Err(StoreError::UnsupportedVersion(current));
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageContent, Segment};
+
+    #[test]
+    fn v11_turns_old_content_into_segments() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for sql in &MIGRATIONS[..10] {
+            conn.execute_batch(sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 10).unwrap();
+        conn.execute_batch(
+            "INSERT INTO chats (id, name, avatar, chat_type) VALUES ('c', 'c', '', 'group');
+             INSERT INTO messages (id, chat_id, sender, avatar, content, message_type) VALUES
+                ('1', 'c', 'a', '', 'hello @Ray', 'text'),
+                ('2', 'c', 'a', '', 'fn main() {}', 'code'),
+                ('3', 'c', 'a', '', '/tmp/cat.png', 'images'),
+                ('4', 'c', 'a', '', 'report.pdf', 'file');",
+        )
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+        let mut stmt = conn.prepare("SELECT content FROM messages ORDER BY id").unwrap();
+        let contents: Vec<MessageContent> = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|raw| MessageContent::from_storage(&raw.unwrap()))
+            .collect();
+        assert_eq!(contents[0], MessageContent::text("hello @Ray"));
+        assert_eq!(
+            contents[1].segments,
+            [Segment::Code {
+                language: None,
+                code: "fn main() {}".to_string(),
+            }]
+        );
+        assert_eq!(contents[2].images().collect::<Vec<_>>(), ["/tmp/cat.png"]);
+        let Segment::File { attachment } = &contents[3].segments[0] else {
+            panic!("expected a file segment, got {:?}", contents[3]);
+        };
+        assert_eq!(attachment.name, "report.pdf");
+    }
+}
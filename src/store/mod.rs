@@ -0,0 +1,600 @@
+mod migrations;
+mod search;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::{
+    backend::{MessagePage, OutboxEntry},
+    Chat, ChatMessage, ChatType, DeliveryState, MessageContent, MessageType, NotificationLevel,
+    UserStatus,
+};
+
+const APP_DIR: &str = "my_lark";
+const DB_FILE: &str = "messages.db";
+
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    Io(std::io::Error),
+    NoDataDir,
+    /// 数据库由更新版本的程序创建
+    UnsupportedVersion(usize),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(err) => write!(f, "sqlite error: {}", err),
+            StoreError::Io(err) => write!(f, "io error: {}", err),
+            StoreError::NoDataDir => write!(f, "no user data directory"),
+            StoreError::UnsupportedVersion(version) => {
+                write!(f, "unsupported schema version: {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Sqlite(err)
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+/// 本地消息库，保存聊天、消息和未读数
+pub struct MessageStore {
+    conn: Mutex<Connection>,
+}
+
+impl MessageStore {
+    /// 打开用户数据目录下的数据库，例如 Linux 上的 `~/.local/share/my_lark/messages.db`
+    pub fn open_default() -> Result<Self, StoreError> {
+        Self::open(Self::default_path()?)
+    }
+
+    pub fn default_path() -> Result<PathBuf, StoreError> {
+        let dir = dirs::data_dir().ok_or(StoreError::NoDataDir)?.join(APP_DIR);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir.join(DB_FILE))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, StoreError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrations::run_migrations(&mut conn)?;
+        let store = Self {
+            conn: Mutex::new(conn),
+        };
+        store.ensure_search_index()?;
+        Ok(store)
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    pub fn is_empty(&self) -> Result<bool, StoreError> {
+        let count: i64 = self
+            .conn()
+            .query_row("SELECT COUNT(*) FROM chats", [], |row| row.get(0))?;
+        Ok(count == 0)
+    }
+
+    pub fn load_chats(&self) -> Result<Vec<Chat>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, avatar, member_count, last_message, chat_type, pin, flagged, done, tags,
+                cloud_doc, notification, nav_pinned, marked_unread, detached
+             FROM chats ORDER BY id",
+        )?;
+        let chats = stmt
+            .query_map([], |row| {
+                Ok(Chat {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    avatar: row.get(2)?,
+                    member_count: row.get(3)?,
+                    last_message: row.get(4)?,
+                    chat_type: chat_type_from_str(&row.get::<_, String>(5)?),
+                    pin: row.get(6)?,
+                    flagged: row.get(7)?,
+                    done: row.get(8)?,
+                    tags: tags_from_str(&row.get::<_, String>(9)?),
+                    cloud_doc: row.get(10)?,
+                    notification: notification_from_str(&row.get::<_, String>(11)?),
+                    nav_pinned: row.get(12)?,
+                    marked_unread: row.get(13)?,
+                    detached: row.get(14)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(chats)
+    }
+
+    pub fn save_chat(&self, chat: &Chat) -> Result<(), StoreError> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO chats (id, name, avatar, member_count, last_message, chat_type, pin, flagged, done, tags,
+                cloud_doc, notification, nav_pinned, marked_unread, detached)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                avatar = excluded.avatar,
+                member_count = excluded.member_count,
+                last_message = excluded.last_message,
+                chat_type = excluded.chat_type,
+                pin = excluded.pin,
+                flagged = excluded.flagged,
+                done = excluded.done,
+                tags = excluded.tags,
+                cloud_doc = excluded.cloud_doc,
+                notification = excluded.notification,
+                nav_pinned = excluded.nav_pinned,
+                marked_unread = excluded.marked_unread,
+                detached = excluded.detached",
+            params![
+                chat.id,
+                chat.name,
+                chat.avatar,
+                chat.member_count,
+                chat.last_message,
+                chat_type_to_str(&chat.chat_type),
+                chat.pin,
+                chat.flagged,
+                chat.done,
+                chat.tags.join("\n"),
+                chat.cloud_doc,
+                notification_to_str(chat.notification),
+                chat.nav_pinned,
+                chat.marked_unread,
+                chat.detached,
+            ],
+        )?;
+        search::index_chat(&conn, chat)?;
+        Ok(())
+    }
+
+    /// 读取 `before`（消息 id）之前的最多 `limit` 条消息，按时间从旧到新返回
+    pub fn load_messages(
+        &self,
+        chat_id: &str,
+        before: Option<&str>,
+        limit: usize,
+    ) -> Result<MessagePage, StoreError> {
+        let conn = self.conn();
+        // 游标为 (sent_at, rowid)，同一毫秒内的消息按写入顺序排
+        let (before_time, before_rowid): (i64, i64) = match before {
+            Some(id) => conn
+                .query_row(
+                    "SELECT sent_at, rowid FROM messages WHERE id = ?1",
+                    [id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?
+                .unwrap_or((i64::MAX, i64::MAX)),
+            None => (i64::MAX, i64::MAX),
+        };
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.chat_id, m.sender, m.avatar, m.content, m.sent_at, m.message_type,
+                    m.client_key, m.delivery, m.reply_to
+             FROM messages m
+             WHERE m.chat_id = ?1 AND (m.sent_at < ?2 OR (m.sent_at = ?2 AND m.rowid < ?3))
+             ORDER BY m.sent_at DESC, m.rowid DESC
+             LIMIT ?4",
+        )?;
+        // 多取一条用来判断是否还有更早的消息
+        let mut messages = stmt
+            .query_map(
+                params![chat_id, before_time, before_rowid, limit as i64 + 1],
+                message_from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        let has_more = messages.len() > limit;
+        messages.truncate(limit);
+        messages.reverse();
+        Ok(MessagePage { messages, has_more })
+    }
+
+    pub fn save_message(&self, message: &ChatMessage) -> Result<(), StoreError> {
+        let conn = self.conn();
+        // 服务端确认后 id 可能变化，先去掉同一幂等键下的本地回显
+        if !message.client_key.is_empty() {
+            search::remove_replaced_messages(&conn, &message.client_key, &message.id)?;
+            conn.execute(
+                "DELETE FROM messages WHERE client_key = ?1 AND id != ?2",
+                params![message.client_key, message.id],
+            )?;
+        }
+        conn.execute(
+            "INSERT INTO messages
+                (id, chat_id, sender, avatar, content, sent_at, message_type, client_key, delivery, reply_to)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT(id) DO UPDATE SET
+                sender = excluded.sender,
+                avatar = excluded.avatar,
+                content = excluded.content,
+                sent_at = excluded.sent_at,
+                message_type = excluded.message_type,
+                client_key = excluded.client_key,
+                delivery = excluded.delivery,
+                reply_to = excluded.reply_to",
+            params![
+                message.id,
+                message.chat_id,
+                message.sender,
+                message.avatar,
+                message.content.to_storage(),
+                message.timestamp.timestamp_millis(),
+                message_type_to_str(&message.message_type),
+                message.client_key,
+                delivery_to_str(message.delivery),
+                message.reply_to,
+            ],
+        )?;
+        search::index_message(&conn, message)?;
+        Ok(())
+    }
+
+    /// 每个聊天最新的一条消息
+    pub fn load_latest_messages(&self) -> Result<HashMap<String, ChatMessage>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.chat_id, m.sender, m.avatar, m.content, m.sent_at, m.message_type,
+                    m.client_key, m.delivery, m.reply_to
+             FROM messages m
+             WHERE m.rowid = (
+                SELECT rowid FROM messages WHERE chat_id = m.chat_id
+                ORDER BY sent_at DESC, rowid DESC LIMIT 1
+             )",
+        )?;
+        let messages = stmt
+            .query_map([], message_from_row)?
+            .map(|message| message.map(|message| (message.chat_id.clone(), message)))
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(messages)
+    }
+
+    /// 读取重启前没有发送成功的消息
+    pub fn load_outbox(&self) -> Result<Vec<OutboxEntry>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.chat_id, m.sender, m.avatar, m.content, m.sent_at, m.message_type,
+                    m.client_key, m.delivery, m.reply_to, o.attempts, o.next_attempt
+             FROM outbox o JOIN messages m ON m.id = o.message_id
+             ORDER BY o.created_at",
+        )?;
+        let entries = stmt
+            .query_map([], |row| {
+                Ok(OutboxEntry {
+                    message: message_from_row(row)?,
+                    attempts: row.get(10)?,
+                    next_attempt: DateTime::from_timestamp_millis(row.get(11)?)
+                        .unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    pub fn save_outbox_entry(&self, entry: &OutboxEntry) -> Result<(), StoreError> {
+        self.conn().execute(
+            "INSERT INTO outbox (client_key, message_id, attempts, next_attempt, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(client_key) DO UPDATE SET
+                attempts = excluded.attempts,
+                next_attempt = excluded.next_attempt",
+            params![
+                entry.message.client_key,
+                entry.message.id,
+                entry.attempts,
+                entry.next_attempt.timestamp_millis(),
+                entry.message.timestamp.timestamp_millis(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_outbox_entry(&self, client_key: &str) -> Result<(), StoreError> {
+        self.conn()
+            .execute("DELETE FROM outbox WHERE client_key = ?1", [client_key])?;
+        Ok(())
+    }
+
+    pub fn load_read_cursors(
+        &self,
+    ) -> Result<HashMap<String, HashMap<String, DateTime<Utc>>>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT chat_id, user, read_at FROM read_cursors")?;
+        let mut cursors: HashMap<String, HashMap<String, DateTime<Utc>>> = HashMap::new();
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (chat_id, user, read_at) = row?;
+            cursors
+                .entry(chat_id)
+                .or_default()
+                .insert(user, DateTime::from_timestamp_millis(read_at).unwrap_or_default());
+        }
+        Ok(cursors)
+    }
+
+    pub fn save_read_cursor(
+        &self,
+        chat_id: &str,
+        user: &str,
+        read_at: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        self.conn().execute(
+            "INSERT INTO read_cursors (chat_id, user, read_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(chat_id, user) DO UPDATE SET read_at = MAX(read_at, excluded.read_at)",
+            params![chat_id, user, read_at.timestamp_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// 每个聊天未读消息里 @ 了 `user` 的条数
+    pub fn load_unread_mentions(&self, user: &str) -> Result<HashMap<String, i32>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT m.chat_id, m.content FROM messages m
+             LEFT JOIN read_cursors r ON r.chat_id = m.chat_id AND r.user = ?1
+             WHERE m.sender != ?1 AND m.sent_at > COALESCE(r.read_at, 0)",
+        )?;
+        let mut chats = HashMap::new();
+        let mut rows = stmt.query([user])?;
+        while let Some(row) = rows.next()? {
+            // 内容是 JSON，解析之后再判断，不依赖序列化的格式
+            let content = MessageContent::from_storage(&row.get::<_, String>(1)?);
+            if content.mentions(user) || content.mentions("所有人") {
+                *chats.entry(row.get(0)?).or_insert(0) += 1;
+            }
+        }
+        Ok(chats)
+    }
+
+    pub fn load_user_status(&self) -> Result<Option<UserStatus>, StoreError> {
+        let status = self
+            .conn()
+            .query_row(
+                "SELECT icon, text, expires_at, do_not_disturb FROM user_status WHERE id = 1",
+                [],
+                |row| {
+                    Ok(UserStatus {
+                        icon: row.get(0)?,
+                        text: row.get(1)?,
+                        expires_at: row
+                            .get::<_, Option<i64>>(2)?
+                            .and_then(DateTime::from_timestamp_millis),
+                        do_not_disturb: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(status)
+    }
+
+    /// 保存或清除自己的状态
+    pub fn save_user_status(&self, status: Option<&UserStatus>) -> Result<(), StoreError> {
+        let conn = self.conn();
+        match status {
+            Some(status) => conn.execute(
+                "INSERT INTO user_status (id, icon, text, expires_at, do_not_disturb)
+                 VALUES (1, ?1, ?2, ?3, ?4)
+                 ON CONFLICT(id) DO UPDATE SET
+                    icon = excluded.icon,
+                    text = excluded.text,
+                    expires_at = excluded.expires_at,
+                    do_not_disturb = excluded.do_not_disturb",
+                params![
+                    status.icon,
+                    status.text,
+                    status.expires_at.map(|at| at.timestamp_millis()),
+                    status.do_not_disturb,
+                ],
+            )?,
+            None => conn.execute("DELETE FROM user_status", [])?,
+        };
+        Ok(())
+    }
+
+    pub fn load_unread_counts(&self) -> Result<HashMap<String, i32>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT chat_id, count FROM unread_counts")?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(counts)
+    }
+
+    pub fn set_unread_count(&self, chat_id: &str, count: i32) -> Result<(), StoreError> {
+        self.conn().execute(
+            "INSERT INTO unread_counts (chat_id, count) VALUES (?1, ?2)
+             ON CONFLICT(chat_id) DO UPDATE SET count = excluded.count",
+            params![chat_id, count],
+        )?;
+        Ok(())
+    }
+}
+
+fn message_from_row(row: &Row) -> rusqlite::Result<ChatMessage> {
+    Ok(ChatMessage {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        sender: row.get(2)?,
+        avatar: row.get(3)?,
+        content: MessageContent::from_storage(&row.get::<_, String>(4)?),
+        timestamp: DateTime::from_timestamp_millis(row.get(5)?).unwrap_or_default(),
+        message_type: message_type_from_str(&row.get::<_, String>(6)?),
+        client_key: row.get(7)?,
+        delivery: delivery_from_str(&row.get::<_, String>(8)?),
+        reply_to: row.get(9)?,
+    })
+}
+
+fn delivery_to_str(delivery: DeliveryState) -> &'static str {
+    match delivery {
+        DeliveryState::Sent => "sent",
+        DeliveryState::Pending => "pending",
+        DeliveryState::Failed => "failed",
+    }
+}
+
+fn delivery_from_str(value: &str) -> DeliveryState {
+    match value {
+        "pending" => DeliveryState::Pending,
+        "failed" => DeliveryState::Failed,
+        _ => DeliveryState::Sent,
+    }
+}
+
+fn chat_type_to_str(chat_type: &ChatType) -> &'static str {
+    match chat_type {
+        ChatType::Group => "group",
+        ChatType::Direct => "direct",
+        ChatType::Bot => "bot",
+        ChatType::TopicGroup => "topic_group",
+        ChatType::Announcement => "announcement",
+    }
+}
+
+fn chat_type_from_str(value: &str) -> ChatType {
+    match value {
+        "group" => ChatType::Group,
+        "direct" => ChatType::Direct,
+        "bot" => ChatType::Bot,
+        "topic_group" => ChatType::TopicGroup,
+        "announcement" => ChatType::Announcement,
+        _ => ChatType::default(),
+    }
+}
+
+fn notification_to_str(level: NotificationLevel) -> &'static str {
+    match level {
+        NotificationLevel::All => "all",
+        NotificationLevel::Mentions => "mentions",
+        NotificationLevel::None => "none",
+    }
+}
+
+fn notification_from_str(value: &str) -> NotificationLevel {
+    match value {
+        "mentions" => NotificationLevel::Mentions,
+        "none" => NotificationLevel::None,
+        _ => NotificationLevel::All,
+    }
+}
+
+fn tags_from_str(value: &str) -> Vec<String> {
+    value
+        .lines()
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn message_type_to_str(message_type: &MessageType) -> &'static str {
+    match message_type {
+        MessageType::Text => "text",
+        MessageType::Images => "images",
+        MessageType::File => "file",
+        MessageType::Code => "code",
+    }
+}
+
+fn message_type_from_str(value: &str) -> MessageType {
+    match value {
+        "images" => MessageType::Images,
+        "file" => MessageType::File,
+        "code" => MessageType::Code,
+        _ => MessageType::Text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Segment;
+
+    #[test]
+    fn chat_menu_state_round_trips() {
+        let store = MessageStore::open_in_memory().unwrap();
+        store
+            .save_chat(&Chat {
+                id: "c".to_string(),
+                name: "Design Review".to_string(),
+                notification: NotificationLevel::Mentions,
+                nav_pinned: true,
+                marked_unread: true,
+                detached: true,
+                ..Default::default()
+            })
+            .unwrap();
+        let chats = store.load_chats().unwrap();
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats[0].notification, NotificationLevel::Mentions);
+        assert!(chats[0].nav_pinned && chats[0].marked_unread && chats[0].detached);
+    }
+
+    #[test]
+    fn unread_mentions_are_found_in_parsed_content() {
+        let store = MessageStore::open_in_memory().unwrap();
+        for id in ["a", "b", "c"] {
+            store
+                .save_chat(&Chat {
+                    id: id.to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let message = |id: &str, chat_id: &str, content: MessageContent| ChatMessage {
+            id: id.to_string(),
+            chat_id: chat_id.to_string(),
+            sender: "Ann".to_string(),
+            timestamp: Utc::now(),
+            message_type: content.message_type(),
+            content,
+            ..Default::default()
+        };
+        let name = "Ray \"R\" Chen";
+        let mention = MessageContent::new(vec![Segment::Mention {
+            user: name.to_string(),
+        }]);
+        store.save_message(&message("1", "a", mention)).unwrap();
+        store
+            .save_message(&message("2", "b", MessageContent::text("hi @所有人")))
+            .unwrap();
+        store
+            .save_message(&message("3", "c", MessageContent::text("Ray Chen")))
+            .unwrap();
+
+        let chats = store.load_unread_mentions(name).unwrap();
+        assert_eq!(chats, HashMap::from([("a".to_string(), 1), ("b".to_string(), 1)]));
+    }
+}
@@ -0,0 +1,312 @@
+use chrono::DateTime;
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension};
+
+use crate::{
+    search::{index_text, SearchHit, SearchKind, SearchQuery, SearchSort},
+    Chat, ChatMessage, MessageType,
+};
+
+use super::{message_type_to_str, MessageStore, StoreError};
+
+impl MessageStore {
+    /// 按搜索语句查询指定类别，最多返回 `limit` 条
+    pub fn search(
+        &self,
+        query: &SearchQuery,
+        kind: SearchKind,
+        sort: SearchSort,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, StoreError> {
+        let expression = query.match_expression();
+        let is_message = matches!(kind, SearchKind::Message | SearchKind::File);
+        if expression.is_none() && !(is_message && query.has_filters()) {
+            return Ok(Vec::new());
+        }
+
+        let mut sql = String::from(
+            "SELECT r.ref_id, search_index.chat_id, search_index.title,
+                    search_index.body, search_index.sent_at
+             FROM search_index
+             JOIN search_refs r ON r.id = search_index.rowid
+             LEFT JOIN messages m ON m.id = r.ref_id
+             WHERE r.kind = ?",
+        );
+        let mut args = vec![Value::from(kind_to_str(kind).to_string())];
+        if let Some(expression) = &expression {
+            sql.push_str(" AND search_index MATCH ?");
+            args.push(Value::from(expression.clone()));
+        }
+        if let Some(chat) = &query.chat {
+            sql.push_str(
+                " AND search_index.chat_id IN
+                    (SELECT id FROM chats WHERE instr(lower(name), lower(?)) > 0)",
+            );
+            args.push(Value::from(chat.clone()));
+        }
+        if let Some(chat_id) = &query.chat_id {
+            sql.push_str(" AND search_index.chat_id = ?");
+            args.push(Value::from(chat_id.clone()));
+        }
+        if is_message {
+            if let Some(from) = &query.from {
+                sql.push_str(" AND search_index.title = ? COLLATE NOCASE");
+                args.push(Value::from(from.clone()));
+            }
+            if !query.has.is_empty() {
+                let placeholders = vec!["?"; query.has.len()].join(", ");
+                sql.push_str(&format!(" AND m.message_type IN ({})", placeholders));
+                args.extend(
+                    query
+                        .has
+                        .iter()
+                        .map(|message_type| Value::from(message_type_to_str(message_type).to_string())),
+                );
+            }
+            if let Some(before) = query.sent_before() {
+                sql.push_str(" AND search_index.sent_at < ?");
+                args.push(Value::from(before.timestamp_millis()));
+            }
+            if let Some(after) = query.sent_after() {
+                sql.push_str(" AND search_index.sent_at >= ?");
+                args.push(Value::from(after.timestamp_millis()));
+            }
+        }
+        if sort == SearchSort::Relevance && expression.is_some() {
+            sql.push_str(" ORDER BY bm25(search_index), search_index.sent_at DESC");
+        } else {
+            sql.push_str(" ORDER BY search_index.sent_at DESC");
+        }
+        // 整句匹配要在取出来之后再过滤，多取一些
+        let fetch = if query.phrases.is_empty() { limit } else { limit * 4 };
+        sql.push_str(" LIMIT ?");
+        args.push(Value::from(fetch as i64));
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&sql)?;
+        let mut hits = stmt
+            .query_map(params_from_iter(args), |row| {
+                Ok(SearchHit {
+                    kind,
+                    ref_id: row.get(0)?,
+                    chat_id: row.get(1)?,
+                    title: row.get(2)?,
+                    body: row.get(3)?,
+                    sent_at: row
+                        .get::<_, Option<i64>>(4)?
+                        .and_then(DateTime::from_timestamp_millis),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        hits.retain(|hit| query.matches_phrases(&hit.body));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// 索引为空但已经有数据时（例如从旧版本升级上来）重新建立索引
+    pub(super) fn ensure_search_index(&self) -> Result<(), StoreError> {
+        let count: i64 =
+            self.conn()
+                .query_row("SELECT COUNT(*) FROM search_index", [], |row| row.get(0))?;
+        if count > 0 {
+            return Ok(());
+        }
+        let chats = self.load_chats()?;
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        for chat in &chats {
+            index_chat(&tx, chat)?;
+        }
+        let mut stmt = tx.prepare(
+            "SELECT id, chat_id, sender, avatar, content, sent_at, message_type, client_key, delivery, reply_to
+             FROM messages",
+        )?;
+        let messages = stmt
+            .query_map([], super::message_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        for message in &messages {
+            index_message(&tx, message)?;
+        }
+        drop(stmt);
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub(super) fn index_chat(conn: &Connection, chat: &Chat) -> Result<(), StoreError> {
+    remove_entries(conn, &[SearchKind::Chat], &chat.id)?;
+    insert_entry(conn, SearchKind::Chat, &chat.id, &chat.id, &chat.name, &chat.name, None)
+}
+
+/// 索引消息本身、文件消息的文件名，以及第一次出现的发送者
+pub(super) fn index_message(conn: &Connection, message: &ChatMessage) -> Result<(), StoreError> {
+    let sent_at = Some(message.timestamp.timestamp_millis());
+    let kind = if message.message_type == MessageType::File {
+        SearchKind::File
+    } else {
+        SearchKind::Message
+    };
+    // 消息类型可能变了，两类都去掉
+    remove_entries(conn, &[SearchKind::Message, SearchKind::File], &message.id)?;
+    insert_entry(
+        conn,
+        kind,
+        &message.id,
+        &message.chat_id,
+        &message.sender,
+        &message.content.plain_text(),
+        sent_at,
+    )?;
+
+    let known: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM search_refs WHERE kind = ?1 AND ref_id = ?2)",
+        params![kind_to_str(SearchKind::Contact), message.sender],
+        |row| row.get(0),
+    )?;
+    if !known {
+        insert_entry(
+            conn,
+            SearchKind::Contact,
+            &message.sender,
+            &message.chat_id,
+            &message.sender,
+            &message.sender,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// 本地回显被服务端版本替换时，去掉旧 id 的索引
+pub(super) fn remove_replaced_messages(
+    conn: &Connection,
+    client_key: &str,
+    id: &str,
+) -> Result<(), StoreError> {
+    let mut stmt =
+        conn.prepare("SELECT id FROM messages WHERE client_key = ?1 AND id != ?2")?;
+    let replaced = stmt
+        .query_map(params![client_key, id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for message_id in replaced {
+        remove_entries(conn, &[SearchKind::Message, SearchKind::File], &message_id)?;
+    }
+    Ok(())
+}
+
+fn remove_entries(conn: &Connection, kinds: &[SearchKind], ref_id: &str) -> Result<(), StoreError> {
+    for kind in kinds {
+        let row: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM search_refs WHERE kind = ?1 AND ref_id = ?2",
+                params![kind_to_str(*kind), ref_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(row) = row {
+            conn.execute("DELETE FROM search_index WHERE rowid = ?1", [row])?;
+            conn.execute("DELETE FROM search_refs WHERE id = ?1", [row])?;
+        }
+    }
+    Ok(())
+}
+
+fn insert_entry(
+    conn: &Connection,
+    kind: SearchKind,
+    ref_id: &str,
+    chat_id: &str,
+    title: &str,
+    body: &str,
+    sent_at: Option<i64>,
+) -> Result<(), StoreError> {
+    conn.execute(
+        "INSERT INTO search_refs (kind, ref_id) VALUES (?1, ?2)",
+        params![kind_to_str(kind), ref_id],
+    )?;
+    conn.execute(
+        "INSERT INTO search_index (rowid, tokens, chat_id, title, body, sent_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            conn.last_insert_rowid(),
+            index_text(body),
+            chat_id,
+            title,
+            body,
+            sent_at
+        ],
+    )?;
+    Ok(())
+}
+
+fn kind_to_str(kind: SearchKind) -> &'static str {
+    match kind {
+        SearchKind::Chat => "chat",
+        SearchKind::Contact => "contact",
+        SearchKind::Message => "message",
+        SearchKind::File => "file",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageContent;
+
+    fn store() -> MessageStore {
+        let store = MessageStore::open_in_memory().unwrap();
+        store
+            .save_chat(&Chat {
+                id: "c".to_string(),
+                name: "Design Review".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        store
+    }
+
+    fn message(id: &str, client_key: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            id: id.to_string(),
+            client_key: client_key.to_string(),
+            chat_id: "c".to_string(),
+            sender: "Alice".to_string(),
+            content: MessageContent::text(text),
+            ..Default::default()
+        }
+    }
+
+    fn search(store: &MessageStore, input: &str, kind: SearchKind) -> Vec<String> {
+        store
+            .search(&SearchQuery::parse(input), kind, SearchSort::Relevance, 10)
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.ref_id)
+            .collect()
+    }
+
+    #[test]
+    fn resaving_a_message_replaces_its_index_entry() {
+        let store = store();
+        store.save_message(&message("1", "", "周五站会改期")).unwrap();
+        store.save_message(&message("1", "", "release notes")).unwrap();
+        assert!(search(&store, "站会", SearchKind::Message).is_empty());
+        assert_eq!(search(&store, "release", SearchKind::Message), ["1"]);
+    }
+
+    #[test]
+    fn confirmed_echo_drops_the_local_entry() {
+        let store = store();
+        store.save_message(&message("local", "k", "deploy done")).unwrap();
+        store.save_message(&message("server", "k", "deploy done")).unwrap();
+        assert_eq!(search(&store, "deploy", SearchKind::Message), ["server"]);
+    }
+
+    #[test]
+    fn indexes_chats_and_each_sender_once() {
+        let store = store();
+        store.save_message(&message("1", "", "hello")).unwrap();
+        store.save_message(&message("2", "", "again")).unwrap();
+        assert_eq!(search(&store, "design", SearchKind::Chat), ["c"]);
+        assert_eq!(search(&store, "alice", SearchKind::Contact), ["Alice"]);
+    }
+}